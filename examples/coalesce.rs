@@ -0,0 +1,71 @@
+//! Time of `IntervalsCollection::insert`, which joins fragments, for growing counts
+//! of fragments. Compared with a linear scan over all free intervals, which
+//! `insert` used before free intervals were indexed by start.
+//!
+//! Run with `cargo run --release --example coalesce`.
+
+use std::time::Instant;
+use subranges::collection::IntervalsCollection;
+use subranges::interval::Interval;
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Gaps between `count` fragments in pseudo-random order.
+fn gaps(count: u64) -> Vec<Interval> {
+    let mut gaps: Vec<_> = (0..count).map(|i| Interval::new(i * 4 + 2, 2)).collect();
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    for i in (1..gaps.len()).rev() {
+        let j = (xorshift(&mut state) % (i as u64 + 1)) as usize;
+        gaps.swap(i, j);
+    }
+    gaps
+}
+
+fn fragments(count: u64) -> impl Iterator<Item = Interval> {
+    (0..count).map(|i| Interval::new(i * 4, 2))
+}
+
+/// Nanoseconds per insert, which joins fragments.
+fn indexed(count: u64) -> f64 {
+    let mut coll: IntervalsCollection = fragments(count).collect();
+    let gaps = gaps(count);
+    let start = Instant::now();
+    for gap in &gaps {
+        coll.insert(*gap);
+    }
+    assert_eq!(coll.count(), 1);
+    start.elapsed().as_nanos() as f64 / count as f64
+}
+
+/// Same as `indexed`, but neighbours are found by scanning every interval.
+fn linear(count: u64) -> f64 {
+    let mut free: Vec<_> = fragments(count).collect();
+    let gaps = gaps(count);
+    let start = Instant::now();
+    for gap in &gaps {
+        let (near, mut rest): (Vec<_>, Vec<_>) = free.iter().partition(|i| i.near(gap));
+        rest.push(near.iter().fold(*gap, |c, i| c.connect(i)));
+        free = rest;
+    }
+    assert_eq!(free.len(), 1);
+    start.elapsed().as_nanos() as f64 / count as f64
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>14} {:>14}",
+        "fragments", "indexed ns", "linear ns"
+    );
+    for count in [1_000, 10_000, 100_000, 1_000_000] {
+        let linear = match count <= 10_000 {
+            true => format!("{:.0}", linear(count)),
+            false => "-".to_string(),
+        };
+        println!("{:>10} {:>14.0} {:>14}", count, indexed(count), linear);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::collection::IntervalsCollection;
+use crate::interval::Interval;
+
+/// Two tiers of free intervals: a small primary collection of recently freed
+/// intervals in front of a large fallback one.
+///
+/// Takes try the primary first and fall through to the fallback. Freed intervals
+/// go to the primary. When the primary holds more than `max_primary` intervals,
+/// its largest ones spill to the fallback.
+#[derive(Debug)]
+pub struct ChainedIntervals {
+    primary: IntervalsCollection,
+    fallback: IntervalsCollection,
+    max_primary: usize,
+}
+
+impl ChainedIntervals {
+    pub fn new(
+        primary: IntervalsCollection,
+        fallback: IntervalsCollection,
+        max_primary: usize,
+    ) -> Self {
+        let mut chained = Self {
+            primary,
+            fallback,
+            max_primary,
+        };
+        chained.spill();
+        chained
+    }
+
+    /// Leftovers, split off a primary interval, may spill to the fallback.
+    pub fn take_exact(&mut self, length: u64) -> Option<Interval> {
+        if let Some(taken) = self.primary.take_exact(length) {
+            self.spill();
+            return Some(taken);
+        }
+        self.fallback.take_exact(length)
+    }
+
+    /// Leftovers, split off a primary interval, may spill to the fallback.
+    pub fn take_exact_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
+        if let Some(taken) = self.primary.take_exact_aligned(length, align) {
+            self.spill();
+            return Some(taken);
+        }
+        self.fallback.take_exact_aligned(length, align)
+    }
+
+    pub fn insert(&mut self, interval: Interval) {
+        self.primary.insert(interval);
+        self.spill();
+    }
+
+    /// Move the largest primary intervals to the fallback, until the primary fits its bound.
+    fn spill(&mut self) {
+        while self.primary.count() > self.max_primary {
+            let largest = *self.primary.iter().last().expect("primary is not empty");
+            self.primary.remove_block(&largest);
+            self.fallback.insert(largest);
+        }
+    }
+
+    /// Total length of free intervals in both tiers.
+    pub fn free_len(&self) -> u64 {
+        self.primary.free_len() + self.fallback.free_len()
+    }
+
+    /// Free intervals of the primary, then of the fallback.
+    pub fn iter(&self) -> impl Iterator<Item = &Interval> {
+        self.primary.iter().chain(self.fallback.iter())
+    }
+
+    pub fn primary(&self) -> &IntervalsCollection {
+        &self.primary
+    }
+
+    pub fn fallback(&self) -> &IntervalsCollection {
+        &self.fallback
+    }
+
+    pub fn into_parts(self) -> (IntervalsCollection, IntervalsCollection) {
+        (self.primary, self.fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chained::ChainedIntervals;
+    use crate::collection::IntervalsCollection;
+    use crate::interval::Interval;
+
+    fn chained() -> ChainedIntervals {
+        let mut fallback = IntervalsCollection::default();
+        fallback.insert(Interval::new(0, 100));
+        ChainedIntervals::new(IntervalsCollection::default(), fallback, 2)
+    }
+
+    #[test]
+    fn primary_first() {
+        let mut chained = chained();
+        let a = chained.take_exact(10).unwrap();
+        assert_eq!(a, Interval::new(0, 10));
+        chained.take_exact(10).unwrap();
+        chained.insert(a);
+        assert_eq!(chained.primary().iter().count(), 1);
+        assert_eq!(chained.take_exact(4).unwrap(), Interval::new(0, 4));
+        assert_eq!(chained.take_exact(7).unwrap(), Interval::new(20, 7));
+        assert_eq!(chained.free_len(), 100 - 21);
+    }
+
+    #[test]
+    fn spill_largest() {
+        let mut chained = chained();
+        let taken: Vec<_> = (0..6).map(|_| chained.take_exact(10).unwrap()).collect();
+        chained.insert(taken[0]);
+        chained.insert(taken[2]);
+        chained.insert(taken[4]);
+        assert_eq!(chained.primary().iter().count(), 2);
+        assert_eq!(chained.fallback().iter().count(), 2);
+        assert_eq!(chained.free_len(), 70);
+    }
+
+    #[test]
+    fn nothing_lost_or_duplicated() {
+        let mut state: u64 = 0x0bad_cafe_dead_beef;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut chained = chained();
+        let mut taken = Vec::new();
+        for _ in 0..2000 {
+            let rnd = next();
+            if rnd % 2 == 0 && !taken.is_empty() {
+                chained.insert(taken.swap_remove((rnd >> 8) as usize % taken.len()));
+            } else if let Some(int) =
+                chained.take_exact_aligned(1 + (rnd >> 8) % 8, 1 << ((rnd >> 16) % 3))
+            {
+                taken.push(int);
+            }
+
+            assert!(chained.primary().iter().count() <= 2);
+            let mut map = [0u8; 100];
+            for int in chained.iter().chain(taken.iter()) {
+                for p in int.start()..int.end() {
+                    map[p as usize] += 1;
+                }
+            }
+            assert!(map.iter().all(|&n| n == 1));
+        }
+    }
+}
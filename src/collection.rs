@@ -1,227 +1,3239 @@
-use crate::interval::Interval;
+use crate::interval::{self, ops, Interval};
+use std::cmp;
 use std::cmp::Ordering;
-use std::collections::BTreeSet;
+use std::collections::{btree_map, BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::iter::FromIterator;
 use std::ops::Bound;
 
-#[derive(Debug)]
+const DEFAULT_SEED: u64 = 0x853c_49e6_748f_ea9b;
+
+/// Policy used to choose among free intervals able to satisfy a take.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum Strategy {
+    /// Take the smallest sufficient interval.
+    #[default]
+    BestFit,
+    /// Take the sufficient interval with the lowest address.
+    FirstFit,
+    /// Take the largest interval. Of equally long ones, the one with the lowest start.
+    WorstFit,
+    /// Take the first sufficient space at or after the cursor left by the
+    /// previous take, wrapping around to the lowest address.
+    NextFit,
+    /// Take a uniformly random sufficient interval. `take_exact` and
+    /// `take_exact_aligned` also place their result at a uniformly random
+    /// (aligned) offset inside it, ignoring `SplitPlacement`. Random numbers
+    /// come from a built-in generator, seeded by `set_seed`.
+    Random,
+}
+
+/// Where an exact take places its result inside the chosen free interval.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum SplitPlacement {
+    /// At the lowest suitable address. The rest stays free after the result.
+    #[default]
+    Front,
+    /// At the highest suitable address. The rest stays free before the result.
+    Back,
+}
+
+/// What happens when a free interval would exceed `IntervalsCollection::max_fragments`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// `insert_checked` fails with `InsertError::TooManyFragments`. The limit is
+    /// checked by `insert_checked` only: `insert` and takes may go past it.
+    RejectInsert,
+    /// After every `insert`, the shortest free intervals (of equally short ones, the
+    /// one with the lowest start) are discarded until the limit holds. The inserted
+    /// interval may be discarded too. Their total length is counted by `dropped_len`.
+    /// Takes never discard free space, so splits may go past the limit until the
+    /// next `insert`.
+    DropSmallest,
+}
+
+/// Free intervals, indexed both by length and by start.
+///
+/// Empty intervals are never stored: inserting one does nothing, and takes of
+/// length 0 return `None`.
+///
+/// `Subranges` is built on `insert`, `take_exact` and `take_exact_aligned`.
+#[derive(Clone)]
 pub struct IntervalsCollection {
     btree: BTreeSet<IntervalLenOrd>,
+    /// The same intervals as `btree`, by start.
+    by_start: BTreeMap<u64, Interval>,
+    /// Total length of intervals in `btree`.
+    free_len: u64,
+    strategy: Strategy,
+    placement: SplitPlacement,
+    cursor: u64,
+    padding_created: u64,
+    rng: u64,
+    /// `aligned_bounds[k] == Some(b)` means, that no take aligned to `1 << k`
+    /// can be longer than `b`. A bound may be too big, but never too small.
+    aligned_bounds: [Option<u64>; 64],
+    /// Remembered by the last `BestFit` aligned take, updated by every `add_free`.
+    aligned_skip: Option<AlignedSkip>,
+    max_fragments: Option<(usize, OverflowPolicy)>,
+    dropped_len: u64,
+    min_remainder: u64,
 }
 
-impl IntervalsCollection {
-    pub fn take_enough(&mut self, length: u64) -> Option<Interval> {
-        let int_len_ord = IntervalLenOrd(Interval::new(0, length));
-        let bounds = (Bound::Included(int_len_ord), Bound::Unbounded);
-        let range = self.btree.range(bounds);
-        let found = range.copied().next();
-        found.map(|i| {
-            self.btree.remove(&i);
-            i.0
-        })
+/// Old name of `IntervalsCollection`.
+#[deprecated(note = "use `IntervalsCollection`")]
+pub type FreeIntervals = IntervalsCollection;
+
+impl IntervalsCollection {
+    /// Empty collection with default settings. Can be used in const context.
+    pub const fn new() -> Self {
+        Self {
+            btree: BTreeSet::new(),
+            by_start: BTreeMap::new(),
+            free_len: 0,
+            strategy: Strategy::BestFit,
+            placement: SplitPlacement::Front,
+            cursor: 0,
+            padding_created: 0,
+            rng: DEFAULT_SEED,
+            aligned_bounds: [None; 64],
+            aligned_skip: None,
+            max_fragments: None,
+            dropped_len: 0,
+            min_remainder: 0,
+        }
+    }
+
+    /// Collection of `intervals`, which must be sorted by start and must not
+    /// intersect. Adjacent intervals are merged. Unlike `collect`, this takes
+    /// `O(n log n)` time with no neighbour searches.
+    /// # Panics
+    /// * In debug builds, panics if `intervals` are unsorted or intersect
+    pub fn from_sorted_disjoint(intervals: impl IntoIterator<Item = Interval>) -> Self {
+        let mut coll = Self::new();
+        let mut last: Option<Interval> = None;
+        for int in intervals.into_iter().filter(|i| !i.is_empty()) {
+            last = match last {
+                Some(prev) => {
+                    debug_assert!(
+                        prev.end() <= int.start(),
+                        "Intervals must be sorted and disjoint"
+                    );
+                    match prev.end() == int.start() {
+                        true => Some(prev.connect(&int)),
+                        false => {
+                            coll.add_free(prev);
+                            Some(int)
+                        }
+                    }
+                }
+                None => Some(int),
+            };
+        }
+        if let Some(last) = last {
+            coll.add_free(last);
+        }
+        coll
+    }
+
+    pub fn take_enough(&mut self, length: u64) -> Option<Interval> {
+        self.take_enough_where(length, |_| true)
+    }
+
+    /// Same as `take_enough`, but only free intervals satisfying `pred` are taken.
+    /// For `Strategy::NextFit`, `pred` sees the part of interval after cursor.
+    /// `pred` must be pure: it may be called any number of times for any interval.
+    pub fn take_enough_where(
+        &mut self,
+        length: u64,
+        pred: impl Fn(&Interval) -> bool,
+    ) -> Option<Interval> {
+        let found = self.find(length, |i| i.len() >= length && pred(i));
+        found.map(|(block, piece)| self.take_piece(block, piece))
+    }
+
+    /// Take free interval, which has at least `length` integers after its first
+    /// multiple of `align`. Returned interval starts at that multiple: the align
+    /// padding before it stays free.
+    pub fn take_enough_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
+        let int = self.take_enough_aligned_block(length, align)?;
+        let start = interval::align_up(int.start(), align).expect("found interval must fit");
+        let aligned = Interval::from_bounds(start, int.end());
+        let pad = Interval::from_bounds(int.start(), start);
+        Some(self.carve_rounded(int, aligned, pad, int))
+    }
+
+    /// Same as `take_enough_aligned`, but padding is taken too.
+    fn take_enough_aligned_block(&mut self, length: u64, align: u64) -> Option<Interval> {
+        let class = Self::align_class(align);
+        let bound = class.and_then(|k| self.aligned_bounds[k]);
+        if bound.is_some_and(|bound| length > bound) {
+            return None;
+        }
+
+        let mut rng = self.rng;
+        let found = self.search_aligned_block(&mut rng, length, align, SplitPlacement::Front);
+        self.rng = rng;
+        match (found, class) {
+            (Some((block, piece)), _) => {
+                self.note_aligned_fit(length, align, &block.0);
+                Some(self.take_piece(block, piece))
+            }
+            (None, Some(k)) if length > 0 => {
+                // Takes only shrink free space, so the bound holds until next insert.
+                self.aligned_bounds[k] = Some(length - 1);
+                None
+            }
+            (None, _) => None,
+        }
+    }
+
+    /// Index of `aligned_bounds` entry for `align`, if it is cached.
+    fn align_class(align: u64) -> Option<usize> {
+        match align.is_power_of_two() {
+            true => Some(align.trailing_zeros() as usize),
+            false => None,
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    pub fn placement(&self) -> SplitPlacement {
+        self.placement
+    }
+
+    /// Set placement used by `take_exact`, `take_exact_aligned` and `take_exact_end_aligned`.
+    pub fn set_placement(&mut self, placement: SplitPlacement) {
+        self.placement = placement;
+    }
+
+    /// Restart random generator, used by `Strategy::Random`, from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = seed;
+    }
+
+    /// Don't leave free parts shorter than `min_remainder` before or after results of
+    /// `take_exact`, `take_exact_aligned`, `take_enough_aligned`, `take_exact_end_aligned`,
+    /// `take_exact_no_cross` and `take_exact_excluding`: they are returned as parts of
+    /// the result instead, unless that would cross `boundary` or take excluded space.
+    /// So result may be longer than requested, and an aligned result may start before
+    /// the aligned address, at `interval::align_up(result.start(), align)`.
+    /// Default 0 means results are never extended.
+    pub fn set_min_remainder(&mut self, min_remainder: u64) {
+        self.min_remainder = min_remainder;
+    }
+
+    pub fn min_remainder(&self) -> u64 {
+        self.min_remainder
+    }
+
+    /// SplitMix64 step.
+    fn next_random(rng: &mut u64) -> u64 {
+        *rng = rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = *rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly random number in `[0; n)`. `n` must be > 0.
+    fn random_below(rng: &mut u64, n: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let r = Self::next_random(rng);
+            if r < zone {
+                return r % n;
+            }
+        }
+    }
+
+    /// Address the next `NextFit` search starts from.
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Restart `NextFit` searches from the lowest address.
+    pub fn reset_cursor(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Remove all free intervals and restart `NextFit` searches.
+    /// Strategy, placement and seed are kept.
+    pub fn clear(&mut self) {
+        self.btree.clear();
+        self.by_start.clear();
+        self.free_len = 0;
+        self.aligned_bounds = [None; 64];
+        self.aligned_skip = None;
+        self.cursor = 0;
+    }
+
+    /// Yield all free intervals in address order. Collection is cleared by this call
+    /// already, so it is empty even if the iterator is dropped early.
+    pub fn drain(&mut self) -> impl Iterator<Item = Interval> + '_ {
+        let by_start = std::mem::take(&mut self.by_start);
+        self.clear();
+        by_start.into_values()
+    }
+
+    /// Same as `clear`, then make `initial` free.
+    pub fn reset(&mut self, initial: Interval) {
+        self.clear();
+        self.insert(initial);
+    }
+
+    /// Save current state. The snapshot isn't affected by later changes of `self`.
+    pub fn snapshot(&self) -> IntervalsSnapshot {
+        IntervalsSnapshot {
+            state: self.clone(),
+        }
+    }
+
+    /// Return to state of `snapshot`: free intervals, settings, cursor and random state.
+    /// `snapshot` may be restored any number of times.
+    pub fn restore(&mut self, snapshot: &IntervalsSnapshot) {
+        self.clone_from(&snapshot.state);
+    }
+
+    /// Total length of alignment pads, split off by aligned takes.
+    pub fn padding_created(&self) -> u64 {
+        self.padding_created
+    }
+
+    /// Limit count of free intervals to `max`, as `policy` describes. Without limit
+    /// by default. With `OverflowPolicy::DropSmallest`, excess intervals are dropped
+    /// right away.
+    pub fn set_max_fragments(&mut self, max: usize, policy: OverflowPolicy) {
+        self.max_fragments = Some((max, policy));
+        self.drop_excess();
+    }
+
+    /// Remove limit, set by `set_max_fragments`. Dropped intervals aren't restored.
+    pub fn remove_max_fragments(&mut self) {
+        self.max_fragments = None;
+    }
+
+    /// Limit of free intervals count and its policy, if set by `set_max_fragments`.
+    pub fn max_fragments(&self) -> Option<(usize, OverflowPolicy)> {
+        self.max_fragments
+    }
+
+    /// Total length of free intervals discarded under `OverflowPolicy::DropSmallest`.
+    pub fn dropped_len(&self) -> u64 {
+        self.dropped_len
+    }
+
+    fn drop_excess(&mut self) {
+        if let Some((max, OverflowPolicy::DropSmallest)) = self.max_fragments {
+            while self.by_start.len() > max {
+                let smallest = self.btree.pop_first().expect("collection isn't empty").0;
+                self.by_start.remove(&smallest.start());
+                self.free_len -= smallest.len();
+                self.dropped_len += smallest.len();
+            }
+        }
+    }
+
+    /// Find a free block (and the part of it to take), which satisfies `fits`.
+    /// `fits` must imply `len() >= length`. Nothing is found for empty `length`.
+    fn find(
+        &mut self,
+        length: u64,
+        fits: impl Fn(&Interval) -> bool,
+    ) -> Option<(IntervalLenOrd, Interval)> {
+        let mut rng = self.rng;
+        let found = self.search(&mut rng, length, fits);
+        self.rng = rng;
+        found
+    }
+
+    /// Same as `find`, but random numbers are taken from `rng`.
+    fn search(
+        &self,
+        rng: &mut u64,
+        length: u64,
+        fits: impl Fn(&Interval) -> bool,
+    ) -> Option<(IntervalLenOrd, Interval)> {
+        if length == 0 {
+            return None;
+        }
+        match self.strategy {
+            Strategy::Random => {
+                let count = self.btree.iter().filter(|i| fits(&i.0)).count() as u64;
+                if count == 0 {
+                    return None;
+                }
+                let index = Self::random_below(rng, count) as usize;
+                let mut fitting = self.btree.iter().filter(|i| fits(&i.0));
+                fitting.nth(index).map(|i| (*i, i.0))
+            }
+            Strategy::BestFit => {
+                let int_len_ord = IntervalLenOrd(Interval::new(0, length));
+                let bounds = (Bound::Included(int_len_ord), Bound::Unbounded);
+                let mut range = self.btree.range(bounds);
+                range.find(|i| fits(&i.0)).map(|i| (*i, i.0))
+            }
+            Strategy::FirstFit => {
+                let mut by_start = self.by_start.values();
+                by_start.find(|i| fits(i)).map(|i| (IntervalLenOrd(*i), *i))
+            }
+            Strategy::WorstFit => {
+                let fitting = self.btree.iter().filter(|i| fits(&i.0));
+                let worst = fitting.max_by(|a, b| {
+                    let by_len = a.0.len().cmp(&b.0.len());
+                    by_len.then(b.0.start().cmp(&a.0.start()))
+                });
+                worst.map(|i| (*i, i.0))
+            }
+            Strategy::NextFit => self.find_next_fit(fits),
+        }
+    }
+
+    /// Blocks lying at or after the cursor are visited first in address order,
+    /// then the search wraps around. A block, which contains the cursor, is
+    /// visited twice: first the part after the cursor, then the whole block.
+    fn find_next_fit(
+        &self,
+        fits: impl Fn(&Interval) -> bool,
+    ) -> Option<(IntervalLenOrd, Interval)> {
+        let cursor = self.cursor;
+        let containing = self.by_start.range(..=cursor).next_back();
+        let containing = containing.filter(|(_, block)| block.end() > cursor);
+        let after = self
+            .by_start
+            .range((Bound::Excluded(cursor), Bound::Unbounded));
+        let forward = containing.into_iter().chain(after).map(|(_, block)| {
+            let start = cmp::max(block.start(), cursor);
+            (*block, Interval::from_bounds(start, block.end()))
+        });
+        let wrapped = self
+            .by_start
+            .range(..cursor)
+            .map(|(_, block)| (*block, *block));
+
+        let mut pieces = forward.chain(wrapped);
+        let found = pieces.find(|(_, piece)| fits(piece));
+        found.map(|(block, piece)| (IntervalLenOrd(block), piece))
+    }
+
+    /// Remove `block` from collection, keeping the part before `piece` free.
+    fn take_piece(&mut self, block: IntervalLenOrd, piece: Interval) -> Interval {
+        self.remove_free(&block.0);
+        if piece.start() > block.0.start() {
+            let prefix = Interval::from_bounds(block.0.start(), piece.start());
+            self.add_free(prefix);
+        }
+        self.cursor = piece.end();
+        piece
+    }
+
+    /// Take exactly `length` integers from a free interval, chosen by strategy.
+    /// The rest of that interval stays free.
+    pub fn take_exact(&mut self, length: u64) -> Option<Interval> {
+        self.take_exact_where(length, |_| true)
+    }
+
+    /// Same as `take_exact`, but only free intervals satisfying `pred` are split.
+    /// `pred` sees the whole candidate, as in `take_enough_where`.
+    pub fn take_exact_where(
+        &mut self,
+        length: u64,
+        pred: impl Fn(&Interval) -> bool,
+    ) -> Option<Interval> {
+        let enough_free_interval = self.take_enough_where(length, pred);
+        enough_free_interval.map(|int| {
+            let start = match (self.strategy, self.placement) {
+                (Strategy::Random, _) => {
+                    int.start() + Self::random_below(&mut self.rng, int.len() - length + 1)
+                }
+                (_, SplitPlacement::Front) => int.start(),
+                (_, SplitPlacement::Back) => int.end() - length,
+            };
+            self.carve_rounded(
+                int,
+                Interval::new(start, length),
+                Interval::new(start, 0),
+                int,
+            )
+        })
+    }
+
+    /// Same as `take_exact`, but start of result is multiple of `align`.
+    /// Padding before the result stays free. `align` 0 and 1 mean no alignment.
+    /// Any other `align` is supported, powers of two are the fastest.
+    /// With `Strategy::BestFit`, of equally short free intervals the one needing
+    /// the smallest pad is taken, so already aligned intervals are preferred.
+    pub fn take_exact_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
+        let mut rng = self.rng;
+        let fit = self.search_aligned(&mut rng, length, align);
+        self.rng = rng;
+        match (fit, Self::align_class(align)) {
+            (Some(fit), _) => {
+                self.note_aligned_fit(length, align, &fit.block.0);
+                let int = self.take_piece(fit.block, fit.piece);
+                Some(self.carve_rounded(int, fit.taken, fit.pad, int))
+            }
+            (None, Some(k)) if length > 0 => {
+                // Takes only shrink free space, so the bound holds until next insert.
+                let bound = self.aligned_bounds[k].map_or(length - 1, |b| cmp::min(b, length - 1));
+                self.aligned_bounds[k] = Some(bound);
+                None
+            }
+            (None, _) => None,
+        }
+    }
+
+    /// Take `take_exact(length)` for every length of `requests`, in order.
+    /// If some take fails, return its index and leave `self` unchanged.
+    pub fn take_multi(&mut self, requests: &[u64]) -> Result<Vec<Interval>, usize> {
+        self.take_all(requests.len(), |coll, i| coll.take_exact(requests[i]))
+    }
+
+    /// Same as `take_multi`, but for `(length, align)` requests of `take_exact_aligned`.
+    pub fn take_multi_aligned(&mut self, requests: &[(u64, u64)]) -> Result<Vec<Interval>, usize> {
+        self.take_all(requests.len(), |coll, i| {
+            let (length, align) = requests[i];
+            coll.take_exact_aligned(length, align)
+        })
+    }
+
+    /// Take `total` integers as few free intervals of at least `min_piece` each,
+    /// longest intervals first. Pieces are taken from starts of free intervals and
+    /// returned in address order. If it's impossible, return `None` without changes.
+    pub fn take_scatter(&mut self, total: u64, min_piece: u64) -> Option<Vec<Interval>> {
+        let min_piece = cmp::max(min_piece, 1);
+        let mut blocks = Vec::new();
+        let mut sum = 0u64;
+        for block in self.btree.iter().rev() {
+            if sum >= total || block.0.len() < min_piece {
+                break;
+            }
+            sum = sum.saturating_add(block.0.len());
+            blocks.push(block.0);
+        }
+        let fit = (blocks.len() as u64).checked_mul(min_piece);
+        if total == 0 || sum < total || fit.map_or(true, |fit| fit > total) {
+            return None;
+        }
+
+        // Leave at least `min_piece` for every next block.
+        let mut need = total;
+        let mut pieces = Vec::with_capacity(blocks.len());
+        for (i, block) in blocks.iter().enumerate() {
+            let rest = (blocks.len() - 1 - i) as u64 * min_piece;
+            let piece = Interval::new(block.start(), cmp::min(block.len(), need - rest));
+            need -= piece.len();
+            self.remove_free(block);
+            pieces.push(self.carve(*block, piece, 0));
+        }
+        pieces.sort();
+        Some(pieces)
+    }
+
+    /// Make `count` takes. On failure put taken intervals back and restore settings
+    /// changed by takes: free intervals are always merged, so they are restored too.
+    fn take_all(
+        &mut self,
+        count: usize,
+        mut take: impl FnMut(&mut Self, usize) -> Option<Interval>,
+    ) -> Result<Vec<Interval>, usize> {
+        let settings = self.take_settings();
+        let mut taken = Vec::with_capacity(count);
+        for i in 0..count {
+            match take(self, i) {
+                Some(int) => taken.push(int),
+                None => {
+                    for int in taken {
+                        self.merge(int);
+                    }
+                    self.restore_take_settings(settings);
+                    return Err(i);
+                }
+            }
+        }
+        Ok(taken)
+    }
+
+    /// Make `take`, but undo it as `take_all` does, if it leaves less than `min_free`
+    /// integers free. Return `None` if `take` fails and `Some(Err(int))` if taken
+    /// `int` is put back.
+    pub(crate) fn take_leaving(
+        &mut self,
+        min_free: u64,
+        take: impl FnOnce(&mut Self) -> Option<Interval>,
+    ) -> Option<Result<Interval, Interval>> {
+        let settings = self.take_settings();
+        let int = take(self)?;
+        if self.free_len >= min_free {
+            return Some(Ok(int));
+        }
+        self.merge(int);
+        self.restore_take_settings(settings);
+        Some(Err(int))
+    }
+
+    fn take_settings(&self) -> TakeSettings {
+        TakeSettings {
+            cursor: self.cursor,
+            rng: self.rng,
+            padding_created: self.padding_created,
+            aligned_bounds: self.aligned_bounds,
+        }
+    }
+
+    fn restore_take_settings(&mut self, settings: TakeSettings) {
+        self.cursor = settings.cursor;
+        self.rng = settings.rng;
+        self.padding_created = settings.padding_created;
+        self.aligned_bounds = settings.aligned_bounds;
+    }
+
+    /// Whether `take_exact_aligned(length, align)` would succeed now.
+    pub fn can_allocate(&self, length: u64, align: u64) -> bool {
+        self.find_fit(length, align).is_some()
+    }
+
+    /// Interval, which `take_exact_aligned(length, align)` would return now, without
+    /// taking it. Both share one search, so they always agree.
+    pub fn find_fit(&self, length: u64, align: u64) -> Option<Interval> {
+        let mut rng = self.rng;
+        let fit = self.search_aligned(&mut rng, length, align);
+        fit.map(|fit| self.rounded(fit.piece, fit.taken, fit.piece))
+    }
+
+    /// Free block (and the part of it to take), which has `length` integers after
+    /// its first multiple of `align`. A remembered `AlignedSkip` lets `BestFit` jump
+    /// over shorter blocks, which are known not to fit.
+    ///
+    /// Of equally short `BestFit` blocks, the one with the smallest pad for
+    /// `placement` is chosen, so aligned blocks are split without pad fragments.
+    fn search_aligned_block(
+        &self,
+        rng: &mut u64,
+        length: u64,
+        align: u64,
+        placement: SplitPlacement,
+    ) -> Option<(IntervalLenOrd, Interval)> {
+        let fits = |i: &Interval| Self::fits_aligned(i, length, align);
+        if self.strategy != Strategy::BestFit {
+            return self.search(rng, length, fits);
+        }
+        if length == 0 {
+            return None;
+        }
+        let skip = self.aligned_skip.filter(|skip| skip.is_for(length, align));
+        let from = IntervalLenOrd(Interval::new(0, skip.map_or(length, |skip| skip.min_len)));
+        let mut range = self.btree.range(from..);
+        let mut best = *range.find(|i| fits(&i.0))?;
+        let mut best_pad = Self::placement_pad(&best.0, length, align, placement);
+        let best_len = best.0.len();
+        let ties = range.take_while(|i| i.0.len() == best_len);
+        for block in ties.filter(|i| fits(&i.0)) {
+            if best_pad == 0 {
+                break;
+            }
+            let pad = Self::placement_pad(&block.0, length, align, placement);
+            if pad < best_pad {
+                best = *block;
+                best_pad = pad;
+            }
+        }
+        Some((best, best.0))
+    }
+
+    /// Integers between `int` bound and `length` integers aligned to `align`,
+    /// placed inside it by `placement`. `int` must fit them.
+    fn placement_pad(int: &Interval, length: u64, align: u64, placement: SplitPlacement) -> u64 {
+        match placement {
+            SplitPlacement::Front => Self::align_pad(int, align),
+            SplitPlacement::Back => {
+                let start = interval::align_down(int.end() - length, align);
+                int.end() - (start + length)
+            }
+        }
+    }
+
+    fn fits_aligned(int: &Interval, length: u64, align: u64) -> bool {
+        int.align_start_up(align)
+            .is_some_and(|aligned| aligned.len() >= length)
+    }
+
+    /// Remember, that `block` is the shortest fit for aligned take, before taking it.
+    fn note_aligned_fit(&mut self, length: u64, align: u64, block: &Interval) {
+        if self.strategy == Strategy::BestFit {
+            self.aligned_skip = Some(AlignedSkip {
+                length,
+                align,
+                min_len: block.len(),
+            });
+        }
+    }
+
+    /// Search of `take_exact_aligned`. Random numbers are taken from `rng`.
+    fn search_aligned(&self, rng: &mut u64, length: u64, align: u64) -> Option<AlignedFit> {
+        let bound = Self::align_class(align).and_then(|k| self.aligned_bounds[k]);
+        if bound.is_some_and(|bound| length > bound) {
+            return None;
+        }
+        let (block, int) = self.search_aligned_block(rng, length, align, self.placement)?;
+        let (start, pad) = match (self.strategy, self.placement) {
+            (Strategy::Random, _) => {
+                let step = cmp::max(align, 1);
+                let first = int.start() + Self::align_pad(&int, align);
+                let count = (int.end() - length - first) / step + 1;
+                let start = first + Self::random_below(rng, count) * step;
+                (start, Interval::from_bounds(int.start(), start))
+            }
+            (_, SplitPlacement::Front) => {
+                let start =
+                    interval::align_up(int.start(), align).expect("found interval must fit");
+                (start, Interval::from_bounds(int.start(), start))
+            }
+            (_, SplitPlacement::Back) => {
+                let start = interval::align_down(int.end() - length, align);
+                (start, Interval::from_bounds(start + length, int.end()))
+            }
+        };
+        Some(AlignedFit {
+            block,
+            piece: int,
+            taken: Interval::new(start, length),
+            pad,
+        })
+    }
+
+    /// Take `length` integers, with end aligned to `align`.
+    /// If `length` is multiple of `align`, result is the same as for `take_exact_aligned`.
+    pub fn take_exact_end_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
+        let found = self.find(length, |i| {
+            Self::end_aligned_start(i, length, align).is_some()
+        });
+        found.map(|(block, piece)| {
+            let int = self.take_piece(block, piece);
+            let (start, pad) = match self.placement {
+                SplitPlacement::Front => {
+                    let start = Self::end_aligned_start(&int, length, align)
+                        .expect("found interval must fit");
+                    (start, Interval::from_bounds(int.start(), start))
+                }
+                SplitPlacement::Back => {
+                    let end = Self::align_down(int.end(), align);
+                    (end - length, Interval::from_bounds(end, int.end()))
+                }
+            };
+            self.carve_rounded(int, Interval::new(start, length), pad, int)
+        })
+    }
+
+    /// First start in `int`, such that end of `length` integers from it is aligned.
+    fn end_aligned_start(int: &Interval, length: u64, align: u64) -> Option<u64> {
+        let end = Self::align_up(int.start().checked_add(length)?, align)?;
+        if end > int.end() {
+            return None;
+        }
+        Some(end - length)
+    }
+
+    /// Take `length` integers, which lie inside one `boundary`-sized window
+    /// `[k * boundary; (k + 1) * boundary)`, with start aligned to `align`.
+    /// Return `None` if `length` > `boundary`. Always uses `SplitPlacement::Front`.
+    pub fn take_exact_no_cross(
+        &mut self,
+        length: u64,
+        align: u64,
+        boundary: u64,
+    ) -> Option<Interval> {
+        if length > boundary {
+            return None;
+        }
+        let found = self.find(length, |i| {
+            Self::no_cross_start(i, length, align, boundary).is_some()
+        });
+        found.map(|(block, piece)| {
+            let int = self.take_piece(block, piece);
+            let start = Self::no_cross_start(&int, length, align, boundary)
+                .expect("found interval must fit");
+            let window_start = start / boundary * boundary;
+            let window = Interval::from_bounds(window_start, window_start.saturating_add(boundary));
+            let pad = Interval::from_bounds(int.start(), start);
+            self.carve_rounded(int, Interval::new(start, length), pad, window)
+        })
+    }
+
+    /// Take `length` integers with start aligned to `align`, which don't intersect
+    /// with any of `excluded` intervals. Excluded space stays free. `excluded` may
+    /// be unsorted and may overlap. Always uses `SplitPlacement::Front`.
+    pub fn take_exact_excluding(
+        &mut self,
+        length: u64,
+        align: u64,
+        excluded: &[Interval],
+    ) -> Option<Interval> {
+        let excluded = interval::normalize(excluded.iter().copied());
+        let found = self.find(length, |i| {
+            Self::excluding_start(i, length, align, &excluded).is_some()
+        });
+        found.map(|(block, piece)| {
+            let int = self.take_piece(block, piece);
+            let (start, gap) = Self::excluding_start(&int, length, align, &excluded)
+                .expect("found interval must fit");
+            let pad = Interval::from_bounds(gap.start(), start);
+            self.carve_rounded(int, Interval::new(start, length), pad, gap)
+        })
+    }
+
+    /// First aligned start in `int` outside of normalized `excluded`, such that `length`
+    /// integers from it don't intersect with `excluded`, and the free gap it lies in.
+    fn excluding_start(
+        int: &Interval,
+        length: u64,
+        align: u64,
+        excluded: &[Interval],
+    ) -> Option<(u64, Interval)> {
+        ops::gaps(excluded.iter().copied(), *int).find_map(|gap| {
+            let start = Self::align_up(gap.start(), align)?;
+            match start.checked_add(length)? <= gap.end() {
+                true => Some((start, gap)),
+                false => None,
+            }
+        })
+    }
+
+    /// First aligned start in `int`, such that `length` integers from it
+    /// don't cross a multiple of `boundary`.
+    fn no_cross_start(int: &Interval, length: u64, align: u64, boundary: u64) -> Option<u64> {
+        let mut start = Self::align_up(int.start(), align)?;
+        loop {
+            let end = start.checked_add(length)?;
+            if end > int.end() {
+                return None;
+            }
+            let window_end = (start / boundary + 1).checked_mul(boundary);
+            match window_end {
+                Some(window_end) if end > window_end => {
+                    start = Self::align_up(window_end, align)?;
+                }
+                _ => return Some(start),
+            }
+        }
+    }
+
+    /// Return `taken` from taken free `block` and put the rest back.
+    /// `padding` of the rest is counted as alignment padding.
+    fn carve(&mut self, block: Interval, taken: Interval, padding: u64) -> Interval {
+        self.put_back_rest(block, taken);
+        self.padding_created += padding;
+        self.cursor = taken.end();
+        taken
+    }
+
+    /// Put parts of taken free `block` outside of `taken` back. Unlike `carve`,
+    /// neither cursor nor padding are changed.
+    fn put_back_rest(&mut self, block: Interval, taken: Interval) {
+        let (prefix, extra) = block.subtract(&taken);
+        for part in prefix.into_iter().chain(extra) {
+            self.add_free(part);
+        }
+    }
+
+    /// Same as `carve`, but free parts shorter than `min_remainder`, which lie inside
+    /// `limit`, are taken too. Alignment padding `pad` is counted only if it stays free.
+    fn carve_rounded(
+        &mut self,
+        block: Interval,
+        taken: Interval,
+        pad: Interval,
+        limit: Interval,
+    ) -> Interval {
+        let rounded = self.rounded(block, taken, limit);
+        let padding = match rounded.contains_interval(&pad) {
+            true => 0,
+            false => pad.len(),
+        };
+        self.carve(block, rounded, padding)
+    }
+
+    /// `taken` from free `block`, extended by free parts of `block` around it, which
+    /// are shorter than `min_remainder` and lie inside `limit`. Nothing is changed.
+    fn rounded(&self, block: Interval, taken: Interval, limit: Interval) -> Interval {
+        let (prefix, extra) = block.subtract(&taken);
+        let short =
+            |part: &Interval| part.len() < self.min_remainder && limit.contains_interval(part);
+        let start = prefix.filter(short).map_or(taken.start(), |p| p.start());
+        let end = extra.filter(short).map_or(taken.end(), |p| p.end());
+        Interval::from_bounds(start, end)
+    }
+
+    /// Move up to `amount` of free space from `self` to `other`, largest intervals first.
+    /// The last moved interval is split if needed. Return amount actually moved.
+    pub fn transfer_to(&mut self, other: &mut IntervalsCollection, amount: u64) -> u64 {
+        self.transfer_within(other, amount, Interval::new(0, u64::MAX))
+    }
+
+    /// Same as `transfer_to`, but moves only free space lying inside `bounds`.
+    pub(crate) fn transfer_within(
+        &mut self,
+        other: &mut IntervalsCollection,
+        amount: u64,
+        bounds: Interval,
+    ) -> u64 {
+        let mut candidates: Vec<_> = self
+            .btree
+            .iter()
+            .filter_map(|block| block.0.intersection(&bounds).map(|piece| (*block, piece)))
+            .collect();
+        candidates.sort_by_key(|(_, piece)| cmp::Reverse(piece.len()));
+
+        // Plan everything before changing any collection.
+        let mut moved = 0;
+        let mut plan = Vec::new();
+        for (block, piece) in candidates {
+            if moved == amount {
+                break;
+            }
+            let length = cmp::min(piece.len(), amount - moved);
+            plan.push((block, Interval::new(piece.start(), length)));
+            moved += length;
+        }
+
+        for (block, piece) in plan {
+            self.remove_free(&block.0);
+            self.put_back_rest(block.0, piece);
+            other.insert(piece);
+        }
+        moved
+    }
+
+    /// Move all free intervals by `delta`.
+    /// Fail without any changes if some interval would leave `u64` bounds.
+    pub fn rebase(&mut self, delta: i64) -> Result<(), RebaseError> {
+        let shifted = self
+            .iter()
+            .map(|i| Self::shifted(i, delta))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.btree = shifted.iter().map(|i| IntervalLenOrd(*i)).collect();
+        self.by_start = shifted.iter().map(|i| (i.start(), *i)).collect();
+        self.cursor = self.cursor.saturating_add_signed(delta);
+        self.aligned_bounds = [None; 64];
+        self.aligned_skip = None;
+        Ok(())
+    }
+
+    pub(crate) fn shifted(int: &Interval, delta: i64) -> Result<Interval, RebaseError> {
+        let error = match delta < 0 {
+            true => RebaseError::Underflow,
+            false => RebaseError::Overflow,
+        };
+        int.shift(delta).ok_or(error)
+    }
+
+    /// Make `interval` free, merging it with adjacent free intervals.
+    /// Takes `O((k + 1) log n)` time for `k` merged intervals.
+    pub fn insert(&mut self, interval: Interval) {
+        self.merge(interval);
+        self.drop_excess();
+    }
+
+    /// Same as `insert`, but the `OverflowPolicy::DropSmallest` limit isn't applied.
+    fn merge(&mut self, interval: Interval) {
+        if interval.is_empty() {
+            return;
+        }
+        let connection = self.connection(&interval);
+        loop {
+            let near = self.near(&connection).next().copied();
+            match near {
+                Some(near) => self.remove_free(&near),
+                None => break,
+            };
+        }
+        self.add_free(connection);
+        self.aligned_bounds = [None; 64];
+    }
+
+    /// Same as `insert`, but fail without changes if `interval` intersects with
+    /// free space, or if it would exceed `OverflowPolicy::RejectInsert` limit.
+    /// Adjacent free intervals are still merged.
+    pub fn insert_checked(&mut self, interval: Interval) -> Result<(), InsertError> {
+        if let Some(block) = self.overlapping(interval).next() {
+            return Err(InsertError::Overlaps(*block));
+        }
+        if let Some((max, OverflowPolicy::RejectInsert)) = self.max_fragments {
+            let adds = !interval.is_empty() && self.near(&interval).next().is_none();
+            if adds && self.count() >= max {
+                return Err(InsertError::TooManyFragments);
+            }
+        }
+        self.insert(interval);
+        Ok(())
+    }
+
+    /// Make every integer of `interval` not free, splitting free intervals as needed.
+    /// Return amount of integers actually removed.
+    pub fn remove(&mut self, interval: Interval) -> u64 {
+        let blocks: Vec<_> = self.overlapping(interval).copied().collect();
+        let mut removed = 0;
+        for block in blocks {
+            if !self.remove_free(&block) {
+                continue;
+            }
+            removed += block.intersection(&interval).map_or(0, |i| i.len());
+            let (before, after) = block.subtract(&interval);
+            for piece in before.into_iter().chain(after) {
+                self.add_free(piece);
+            }
+        }
+        removed
+    }
+
+    /// Keep only free intervals, for which `pred` returns `true`, visiting them in
+    /// address order. Return total length of removed intervals.
+    pub fn retain(&mut self, mut pred: impl FnMut(&Interval) -> bool) -> u64 {
+        let dropped: Vec<_> = self
+            .by_start
+            .values()
+            .filter(|i| !pred(i))
+            .copied()
+            .collect();
+        for int in &dropped {
+            self.remove_free(int);
+        }
+        dropped.iter().map(|i| i.len()).sum()
+    }
+
+    /// Same as `retain`, keeping free intervals of at least `min_len` integers.
+    pub fn retain_at_least(&mut self, min_len: u64) -> u64 {
+        self.retain(|i| i.len() >= min_len)
+    }
+
+    /// Take `interval` out of the free interval, which contains it whole.
+    /// Return `false` if there is no such free interval. Empty `interval` is always
+    /// taken, without changes.
+    pub(crate) fn carve_at(&mut self, interval: Interval) -> bool {
+        if interval.is_empty() {
+            return true;
+        }
+        match self.containing(&interval).copied() {
+            Some(block) => {
+                self.remove_free(&block);
+                self.carve(block, interval, 0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Longest free interval. Of equally long ones, the one with the lowest start.
+    pub fn peek_max(&self) -> Option<Interval> {
+        let max_len = self.btree.iter().next_back()?.0.len();
+        let first_max = IntervalLenOrd(Interval::new(0, max_len));
+        self.btree.range(first_max..).next().map(|int| int.0)
+    }
+
+    /// Same as `peek_max`.
+    pub fn largest_free(&self) -> Option<Interval> {
+        self.peek_max()
+    }
+
+    /// Shortest free interval. Of equally short ones, the one with the lowest start.
+    pub fn smallest_free(&self) -> Option<Interval> {
+        self.btree.iter().next().map(|int| int.0)
+    }
+
+    /// Remove and return free interval, chosen by `peek_max`.
+    pub fn take_max(&mut self) -> Option<Interval> {
+        let max = self.peek_max()?;
+        self.remove_free(&max);
+        Some(max)
+    }
+
+    /// Take exactly `interval`, splitting the free interval, which contains it.
+    /// Return error if some part of `interval` isn't free. Empty `interval` is always taken.
+    pub fn take_at(&mut self, interval: Interval) -> Result<Interval, TakeAtError> {
+        if self.carve_at(interval) {
+            return Ok(interval);
+        }
+        match self.coverage(&interval) {
+            Coverage::Partial(_) => Err(TakeAtError::PartiallyFree),
+            _ => Err(TakeAtError::NotFree),
+        }
+    }
+
+    /// Remove exactly `int` from free intervals. Return `false` if it isn't a free interval.
+    pub(crate) fn remove_block(&mut self, int: &Interval) -> bool {
+        self.remove_free(int)
+    }
+
+    fn add_free(&mut self, int: Interval) {
+        self.btree.insert(IntervalLenOrd(int));
+        self.by_start.insert(int.start(), int);
+        self.free_len += int.len();
+        if let Some(skip) = &mut self.aligned_skip {
+            if int.len() < skip.min_len && Self::fits_aligned(&int, skip.length, skip.align) {
+                skip.min_len = int.len();
+            }
+        }
+    }
+
+    fn remove_free(&mut self, int: &Interval) -> bool {
+        let removed = self.btree.remove(&IntervalLenOrd(*int));
+        if removed {
+            self.by_start.remove(&int.start());
+            self.free_len -= int.len();
+        }
+        removed
+    }
+
+    /// Return free interval, which `insert(interval)` would produce, without inserting.
+    pub fn simulate_insert(&self, interval: Interval) -> Interval {
+        self.connection(&interval)
+    }
+
+    /// Same as `simulate_insert`, also returning the length of the largest
+    /// free interval after insertion.
+    /// Takes `O((k + 1) log n)` time for `k` free intervals, joined with `interval`.
+    pub(crate) fn simulate_insert_with_largest(&self, interval: Interval) -> (Interval, u64) {
+        let connection = self.connection(&interval);
+        // Joined intervals lie inside `connection`, others don't intersect with it.
+        let largest_other = self
+            .btree
+            .iter()
+            .rev()
+            .find(|int| !connection.contains_interval(&int.0))
+            .map_or(0, |int| int.0.len());
+        (connection, cmp::max(connection.len(), largest_other))
+    }
+
+    /// Union of `interval` with free intervals, which would join with it on insert.
+    fn connection(&self, interval: &Interval) -> Interval {
+        self.near(interval)
+            .fold(*interval, |connection, int| connection.connect(int))
+    }
+
+    /// Free intervals, which are near to `interval`, in address order. Free intervals
+    /// are never near to each other, so these are all intervals `insert` joins.
+    /// Takes `O(log n + k)` time for `k` such intervals.
+    fn near(&self, interval: &Interval) -> impl Iterator<Item = &Interval> {
+        let before = self.prev_free_before(interval.start());
+        let before = before.filter(|int| int.end() >= interval.start());
+        let after = self.by_start.range(interval.start()..=interval.end());
+        before.into_iter().chain(after.map(|(_, int)| int))
+    }
+
+    fn align_up(value: u64, align: u64) -> Option<u64> {
+        interval::align_up(value, align)
+    }
+
+    fn align_down(value: u64, align: u64) -> u64 {
+        interval::align_down(value, align)
+    }
+
+    /// Distance from start of `int` to the next multiple of `align`.
+    /// `u64::MAX` if there is no such multiple.
+    fn align_pad(int: &Interval, align: u64) -> u64 {
+        interval::align_up(int.start(), align).map_or(u64::MAX, |start| start - int.start())
+    }
+
+    /// Total length of free intervals. Takes `O(1)` time.
+    pub fn free_len(&self) -> u64 {
+        self.free_len
+    }
+
+    /// `1 - largest / total` of free interval lengths: 0 if free space is one interval
+    /// or there is none, close to 1 if it is split into many small intervals.
+    /// Takes `O(log n)` time.
+    pub fn fragmentation(&self) -> f64 {
+        let total = self.free_len();
+        match self.largest_free() {
+            Some(largest) => 1.0 - largest.len() as f64 / total as f64,
+            None => 0.0,
+        }
+    }
+
+    /// `small / total`, where `small` is total length of free intervals shorter than
+    /// `len`: fraction of free space, unusable for takes of `len`. 0 if there is no
+    /// free space. Takes `O(n)` time.
+    pub fn external_fragmentation_for(&self, len: u64) -> f64 {
+        let total = self.free_len();
+        if total == 0 {
+            return 0.0;
+        }
+        let shorter = self.btree.range(..IntervalLenOrd(Interval::new(0, len)));
+        let small: u64 = shorter.map(|int| int.0.len()).sum();
+        small as f64 / total as f64
+    }
+
+    /// Number of free intervals.
+    pub fn count(&self) -> usize {
+        self.btree.len()
+    }
+
+    /// True if there are no free intervals.
+    pub fn is_empty(&self) -> bool {
+        self.btree.is_empty()
+    }
+
+    /// Free intervals by ascending length. Of equally long ones, lower start goes first.
+    pub fn iter(&self) -> impl Iterator<Item = &Interval> {
+        self.btree.iter().map(|i| &i.0)
+    }
+
+    /// Free intervals by ascending start.
+    pub fn iter_by_position(&self) -> impl Iterator<Item = &Interval> {
+        self.by_start.values()
+    }
+
+    /// Free intervals, which intersect with `query`, in address order.
+    /// Takes `O(log n + k)` time for `k` such intervals.
+    fn overlapping(&self, query: Interval) -> impl Iterator<Item = &Interval> {
+        let before = self.prev_free_before(query.start());
+        let before = before.filter(|int| !query.is_empty() && int.end() > query.start());
+        let inside = self.by_start.range(query.start()..query.end());
+        before.into_iter().chain(inside.map(|(_, int)| int))
+    }
+
+    /// Free interval, which contains whole non-empty `interval`. Takes `O(log n)` time.
+    fn containing(&self, interval: &Interval) -> Option<&Interval> {
+        let (_, block) = self.by_start.range(..=interval.start()).next_back()?;
+        match interval.end() <= block.end() {
+            true => Some(block),
+            false => None,
+        }
+    }
+
+    /// Free interval, which contains `p`. Takes `O(log n)` time.
+    pub fn containing_free_block(&self, p: u64) -> Option<&Interval> {
+        let (_, block) = self.by_start.range(..=p).next_back()?;
+        match block.contains(p) {
+            true => Some(block),
+            false => None,
+        }
+    }
+
+    /// Free interval with the lowest start, which contains `p` or starts after it.
+    /// Takes `O(log n)` time.
+    pub fn next_free_at_or_after(&self, p: u64) -> Option<&Interval> {
+        let containing = self.containing_free_block(p);
+        containing.or_else(|| self.by_start.range(p..).next().map(|(_, int)| int))
+    }
+
+    /// Free interval with the highest start, which starts before `p`. It may contain `p`.
+    /// Takes `O(log n)` time.
+    pub fn prev_free_before(&self, p: u64) -> Option<&Interval> {
+        self.by_start.range(..p).next_back().map(|(_, int)| int)
+    }
+
+    /// Parts of free intervals inside `range`, in address order.
+    /// Takes `O(log n + k)` time for `k` yielded intervals.
+    pub fn intersecting(&self, range: Interval) -> impl Iterator<Item = Interval> + '_ {
+        self.overlapping(range)
+            .filter_map(move |i| i.intersection(&range))
+    }
+
+    /// Maximal parts of `bounds`, not covered by free intervals, in address order.
+    /// Together with `iter_by_position` they tile `bounds`.
+    /// Takes `O(log n + k)` time for `k` free intervals, which intersect with `bounds`.
+    pub fn gaps(&self, bounds: Interval) -> impl Iterator<Item = Interval> + '_ {
+        ops::gaps(self.intersecting(bounds), bounds)
+    }
+
+    /// Total length of free space inside `query`.
+    pub fn free_within(&self, query: Interval) -> u64 {
+        self.intersecting(query).map(|i| i.len()).sum()
+    }
+
+    /// How much of `interval` is free. Takes `O(log n + k)` time for `k` free
+    /// intervals, which intersect with it. Empty `interval` is `Coverage::Free`.
+    pub fn coverage(&self, interval: &Interval) -> Coverage {
+        match self.free_within(*interval) {
+            free if free == interval.len() => Coverage::Free,
+            0 => Coverage::None,
+            free => Coverage::Partial(free),
+        }
+    }
+
+    /// True if every integer of `interval` is free. Takes `O(log n)` time.
+    pub fn is_fully_free(&self, interval: &Interval) -> bool {
+        interval.is_empty() || self.containing(interval).is_some()
+    }
+
+    /// Deterministic hash of free intervals, stable across versions and platforms.
+    ///
+    /// 64-bit FNV-1a over `start` and `len()` of every free interval, in order of
+    /// starts, each written as 8 little-endian bytes. Depends only on the set of free
+    /// integers, not on how it was produced.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        self.hash_free(&mut hasher);
+        hasher.finish()
+    }
+
+    pub(crate) fn hash_free(&self, hasher: &mut StateHasher) {
+        for int in self.iter_by_position() {
+            hasher.write(int.start());
+            hasher.write(int.len());
+        }
+    }
+}
+
+/// 64-bit FNV-1a over little-endian `u64`s.
+pub(crate) struct StateHasher(u64);
+
+impl StateHasher {
+    pub(crate) fn write(&mut self, value: u64) {
+        for byte in value.to_le_bytes() {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+/// Free intervals are listed by position. Cached search bounds are omitted.
+impl fmt::Debug for IntervalsCollection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let free: Vec<_> = self.iter_by_position().collect();
+        f.debug_struct("IntervalsCollection")
+            .field("free", &free)
+            .field("strategy", &self.strategy)
+            .field("placement", &self.placement)
+            .field("cursor", &self.cursor)
+            .field("padding_created", &self.padding_created)
+            .field("dropped_len", &self.dropped_len)
+            .field("rng", &self.rng)
+            .finish()
+    }
+}
+
+/// Formats as `free: [0..16) [64..128) (2 blocks, 80 total)`, intervals by position.
+/// `{:#}` puts the summary first and every interval on its own line.
+impl fmt::Display for IntervalsCollection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = format!("({} blocks, {} total)", self.count(), self.free_len());
+        match f.alternate() {
+            true => {
+                write!(f, "free: {}", summary)?;
+                for int in self.iter_by_position() {
+                    write!(f, "\n  [{})", int)?;
+                }
+                Ok(())
+            }
+            false => {
+                f.write_str("free:")?;
+                for int in self.iter_by_position() {
+                    write!(f, " [{})", int)?;
+                }
+                write!(f, " {}", summary)
+            }
+        }
+    }
+}
+
+impl Default for IntervalsCollection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collections are equal if they have the same free integers. Free intervals are
+/// always merged, so then they have the same free intervals too. Settings, cursor
+/// and random state aren't compared.
+impl PartialEq for IntervalsCollection {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_start == other.by_start
+    }
+}
+
+impl Eq for IntervalsCollection {}
+
+/// Same as `insert` of every interval.
+impl Extend<Interval> for IntervalsCollection {
+    fn extend<T: IntoIterator<Item = Interval>>(&mut self, iter: T) {
+        for int in iter {
+            self.insert(int);
+        }
+    }
+}
+
+/// Free intervals in address order.
+impl IntoIterator for IntervalsCollection {
+    type Item = Interval;
+    type IntoIter = btree_map::IntoValues<u64, Interval>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.by_start.into_values()
+    }
+}
+
+/// Same as `insert` of every interval into empty collection.
+impl FromIterator<Interval> for IntervalsCollection {
+    fn from_iter<T: IntoIterator<Item = Interval>>(iter: T) -> Self {
+        let mut coll = Self::new();
+        coll.extend(iter);
+        coll
+    }
+}
+
+/// Saved state of `IntervalsCollection`, see `IntervalsCollection::snapshot`.
+#[derive(Debug, Clone)]
+pub struct IntervalsSnapshot {
+    state: IntervalsCollection,
+}
+
+/// Error of moving intervals by `rebase`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RebaseError {
+    /// Some interval would start below zero.
+    Underflow,
+    /// Some interval would end above `u64::MAX`.
+    Overflow,
+}
+
+impl fmt::Display for RebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebaseError::Underflow => f.write_str("rebased interval would start below zero"),
+            RebaseError::Overflow => f.write_str("rebased interval would end above u64::MAX"),
+        }
+    }
+}
+
+impl Error for RebaseError {}
+
+/// Result of `IntervalsCollection::coverage`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Coverage {
+    /// Every integer is free.
+    Free,
+    /// No integer is free.
+    None,
+    /// Only this many integers are free.
+    Partial(u64),
+}
+
+/// Error of `insert_checked`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InsertError {
+    /// Inserted interval intersects with this free interval.
+    Overlaps(Interval),
+    /// Inserted interval would exceed the limit of free intervals.
+    TooManyFragments,
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::Overlaps(block) => write!(f, "interval overlaps free interval {}", block),
+            InsertError::TooManyFragments => write!(f, "too many free intervals"),
+        }
+    }
+}
+
+impl Error for InsertError {}
+
+/// Error of `take_at`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TakeAtError {
+    /// No integer of the interval is free.
+    NotFree,
+    /// Some integers of the interval are free, but not all.
+    PartiallyFree,
+}
+
+impl fmt::Display for TakeAtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TakeAtError::NotFree => f.write_str("interval is outside of free space"),
+            TakeAtError::PartiallyFree => f.write_str("interval is partially taken"),
+        }
+    }
+}
+
+impl Error for TakeAtError {}
+
+/// Settings changed by takes, which are restored when takes are undone.
+struct TakeSettings {
+    cursor: u64,
+    rng: u64,
+    padding_created: u64,
+    aligned_bounds: [Option<u64>; 64],
+}
+
+/// Placement found by `search_aligned`: `taken` lies in `piece` of free `block`.
+struct AlignedFit {
+    block: IntervalLenOrd,
+    piece: Interval,
+    taken: Interval,
+    pad: Interval,
+}
+
+/// No free interval shorter than `min_len` fits `length` integers aligned to `align`.
+#[derive(Copy, Clone)]
+struct AlignedSkip {
+    length: u64,
+    align: u64,
+    min_len: u64,
+}
+
+impl AlignedSkip {
+    fn is_for(&self, length: u64, align: u64) -> bool {
+        self.length == length && self.align == align
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+struct IntervalLenOrd(Interval);
+
+impl PartialOrd for IntervalLenOrd {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for IntervalLenOrd {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&self.0.len(), &other.0.len()).then(Ord::cmp(&self.0.start(), &other.0.start()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collection::{
+        Coverage, InsertError, IntervalsCollection, OverflowPolicy, SplitPlacement, Strategy,
+        TakeAtError,
+    };
+    use crate::interval::{self, Interval};
+
+    fn test_data() -> IntervalsCollection {
+        let mut coll = IntervalsCollection::default();
+        let free_interval = Interval::new(0, 10);
+        coll.insert(free_interval);
+        coll
+    }
+
+    #[test]
+    fn take_enough() {
+        let mut coll = test_data();
+        let seven_len = coll.take_enough(7).unwrap();
+        assert!(seven_len.len() > 7);
+        assert!(coll.take_enough(1).is_none());
+    }
+
+    #[test]
+    fn insert_non_intersect() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        let mut it = coll.iter();
+        assert_eq!(it.next().unwrap().len(), 5);
+        assert_eq!(it.next().unwrap().len(), 10);
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn insert_intersect() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        coll.insert(Interval::new(8, 10));
+        let mut it = coll.iter();
+        assert_eq!(it.next().unwrap().len(), 20);
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn take_enough_none() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        assert!(coll.take_enough(15).is_none());
+    }
+
+    #[test]
+    fn take_enough_twice() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        coll.insert(Interval::new(25, 3));
+        assert_eq!(coll.take_enough(3).unwrap().len(), 3);
+        assert_eq!(coll.take_enough(5).unwrap().len(), 5);
+        assert_eq!(coll.take_enough(10).unwrap().len(), 10);
+        assert!(coll.take_enough(1).is_none());
+    }
+
+    #[test]
+    fn take_exact() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        coll.insert(Interval::new(25, 3));
+        assert_eq!(coll.take_exact(2).unwrap().len(), 2);
+        assert_eq!(coll.take_exact(2).unwrap().len(), 2);
+        let mut it = coll.iter();
+        assert_eq!(it.next().unwrap().len(), 1);
+        assert_eq!(it.next().unwrap().len(), 3);
+        assert_eq!(it.next().unwrap().len(), 10);
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn take_exact_align() {
+        let mut coll = IntervalsCollection::default();
+        let free_interval = Interval::new(0, 30);
+        coll.insert(free_interval);
+
+        let len = 15;
+        let align = 2;
+        let int = coll.take_exact_aligned(len, align).unwrap();
+        assert_eq!(int.start() % align, 0);
+        assert_eq!(int.len(), len);
+
+        let len = 4;
+        let align = 4;
+        let int = coll.take_exact_aligned(len, align).unwrap();
+        assert_eq!(int.start() % align, 0);
+        assert_eq!(int.len(), len);
+
+        let mut iter = coll.iter();
+        assert_eq!(*iter.next().unwrap(), Interval::new(15, 1));
+        assert_eq!(*iter.next().unwrap(), Interval::new(20, 10));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn take_exact_none() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 5));
+        coll.insert(Interval::new(25, 3));
+        assert!(coll.take_exact(20).is_none());
+    }
+
+    #[test]
+    fn insert_same_length() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(15, 10));
+        let mut it = coll.iter();
+        assert_eq!(*it.next().unwrap(), Interval::new(0, 10));
+        assert_eq!(*it.next().unwrap(), Interval::new(15, 10));
+        assert!(it.next().is_none());
+    }
+
+    fn take_and_free(coll: &mut IntervalsCollection, length: u64) -> u64 {
+        let int = coll.take_exact(length).unwrap();
+        coll.insert(int);
+        int.start()
+    }
+
+    #[test]
+    fn best_fit_reuses_low_addresses() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 30));
+        let starts: Vec<_> = (0..7).map(|_| take_and_free(&mut coll, 5)).collect();
+        assert_eq!(starts, vec![0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn next_fit_rotates() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::NextFit);
+        coll.insert(Interval::new(0, 30));
+        let starts: Vec<_> = (0..7).map(|_| take_and_free(&mut coll, 5)).collect();
+        assert_eq!(starts, vec![0, 5, 10, 15, 20, 25, 0]);
+        assert_eq!(coll.iter().count(), 1);
+    }
+
+    #[test]
+    fn next_fit_wraps_and_resets() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::NextFit);
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+
+        assert_eq!(coll.take_exact(4).unwrap(), Interval::new(0, 4));
+        assert_eq!(coll.take_exact(4).unwrap(), Interval::new(4, 4));
+        assert_eq!(coll.take_exact(4).unwrap(), Interval::new(20, 4));
+        assert_eq!(coll.cursor(), 24);
+
+        // Nothing after the cursor fits, so the search wraps.
+        coll.insert(Interval::new(0, 8));
+        assert_eq!(coll.take_exact(7).unwrap(), Interval::new(0, 7));
+
+        coll.insert(Interval::new(0, 7));
+        coll.reset_cursor();
+        assert_eq!(coll.take_exact(2).unwrap(), Interval::new(0, 2));
+    }
+
+    #[test]
+    fn next_fit_aligned() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::NextFit);
+        coll.insert(Interval::new(0, 32));
+        assert_eq!(coll.take_exact_aligned(3, 4).unwrap(), Interval::new(0, 3));
+        assert_eq!(coll.take_exact_aligned(3, 4).unwrap(), Interval::new(4, 3));
+        assert_eq!(coll.iter().map(|i| i.len()).sum::<u64>(), 26);
+    }
+
+    #[test]
+    fn padding_coalesces_on_insert() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(1, 29));
+
+        let a = coll.take_exact_aligned(8, 4).unwrap();
+        assert_eq!(a, Interval::new(4, 8));
+        assert_eq!(coll.padding_created(), 3);
+        let b = coll.take_exact_aligned(3, 8).unwrap();
+        assert_eq!(b, Interval::new(16, 3));
+        assert_eq!(coll.padding_created(), 7);
+
+        coll.insert(a);
+        assert_eq!(coll.iter().count(), 2);
+        coll.insert(b);
+        let mut it = coll.iter();
+        assert_eq!(*it.next().unwrap(), Interval::new(1, 29));
+        assert!(it.next().is_none());
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::Random);
+        coll.insert(Interval::new(1, 1000));
+        let a = coll.take_exact_aligned(16, 16).unwrap();
+        assert_eq!(a.start() % 16, 0);
+        assert_eq!(coll.padding_created(), a.start() - 1);
+    }
+
+    #[test]
+    fn take_exact_no_cross() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(10, 30));
+
+        assert!(coll.take_exact_no_cross(17, 1, 16).is_none());
+        let int = coll.take_exact_no_cross(8, 1, 16).unwrap();
+        assert_eq!(int, Interval::new(16, 8));
+        let int = coll.take_exact_no_cross(6, 4, 16).unwrap();
+        assert_eq!(int, Interval::new(24, 6));
+        assert!(coll.take_exact_no_cross(12, 1, 16).is_none());
+
+        let mut it = coll.iter();
+        assert_eq!(*it.next().unwrap(), Interval::new(10, 6));
+        assert_eq!(*it.next().unwrap(), Interval::new(30, 10));
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn take_exact_excluding() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 30));
+        let excluded = [
+            Interval::new(28, 6),
+            Interval::new(2, 4),
+            Interval::new(30, 2),
+        ];
+
+        let int = coll.take_exact_excluding(8, 1, &excluded).unwrap();
+        assert_eq!(int, Interval::new(20, 8));
+        let int = coll.take_exact_excluding(8, 4, &excluded).unwrap();
+        assert_eq!(int, Interval::new(36, 8));
+        assert_eq!(coll.padding_created(), 2);
+        assert!(coll.take_exact_excluding(7, 1, &excluded).is_none());
+
+        let mut it = coll.iter();
+        assert_eq!(*it.next().unwrap(), Interval::new(44, 6));
+        assert_eq!(*it.next().unwrap(), Interval::new(28, 8));
+        assert_eq!(*it.next().unwrap(), Interval::new(0, 10));
+        assert!(it.next().is_none());
+        drop(it);
+        assert_eq!(coll.take_exact(7), Some(Interval::new(28, 7)));
+
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 64));
+        let excluded = [
+            Interval::from_bounds(25, 40),
+            Interval::from_bounds(10, 30),
+            Interval::from_bounds(12, 14),
+            Interval::from_bounds(40, 42),
+            Interval::from_bounds(15, 15),
+        ];
+        let int = coll.take_exact_excluding(20, 1, &excluded);
+        assert_eq!(int, Some(Interval::new(42, 20)));
+        let int = coll.take_exact_excluding(10, 1, &excluded);
+        assert_eq!(int, Some(Interval::new(0, 10)));
+        assert_eq!(coll.take_exact_excluding(3, 1, &excluded), None);
+        assert_eq!(coll.free_len(), 64 - 30);
+    }
+
+    #[test]
+    fn free_within() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+
+        assert_eq!(coll.free_within(Interval::new(5, 20)), 10);
+        assert_eq!(coll.free_within(Interval::new(2, 4)), 4);
+        assert_eq!(coll.free_within(Interval::new(10, 10)), 0);
+        assert_eq!(coll.free_within(Interval::new(25, 100)), 5);
+        assert_eq!(coll.free_within(Interval::new(40, 10)), 0);
+        assert_eq!(coll.free_within(Interval::new(5, 0)), 0);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+
+        assert_eq!(coll.coverage(&Interval::new(2, 4)), Coverage::Free);
+        assert_eq!(coll.coverage(&Interval::new(20, 10)), Coverage::Free);
+        assert_eq!(coll.coverage(&Interval::new(10, 10)), Coverage::None);
+        assert_eq!(coll.coverage(&Interval::new(5, 20)), Coverage::Partial(10));
+        assert_eq!(coll.coverage(&Interval::new(29, 2)), Coverage::Partial(1));
+        assert_eq!(coll.coverage(&Interval::new(15, 0)), Coverage::Free);
+
+        assert!(coll.is_fully_free(&Interval::new(0, 10)));
+        assert!(coll.is_fully_free(&Interval::new(25, 5)));
+        assert!(coll.is_fully_free(&Interval::new(15, 0)));
+        assert!(!coll.is_fully_free(&Interval::new(5, 6)));
+        assert!(!coll.is_fully_free(&Interval::new(12, 2)));
+        assert!(!coll.is_fully_free(&Interval::new(29, 2)));
+
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..500 {
+            let query = Interval::new(xorshift(&mut state) % 40, xorshift(&mut state) % 16);
+            let slow: u64 = (query.start()..query.end())
+                .filter(|p| coll.iter().any(|i| i.contains(*p)))
+                .count() as u64;
+            assert_eq!(coll.free_within(query), slow);
+            assert_eq!(coll.is_fully_free(&query), slow == query.len());
+        }
+    }
+
+    #[test]
+    fn iter_by_position() {
+        let mut coll = IntervalsCollection::default();
+        for (start, len) in [(52, 1), (0, 8), (20, 30), (60, 8)].iter() {
+            coll.insert(Interval::new(*start, *len));
+        }
+        let lens: Vec<_> = coll.iter().map(|i| i.len()).collect();
+        assert_eq!(lens, [1, 8, 8, 30]);
+        let starts: Vec<_> = coll.iter_by_position().map(|i| i.start()).collect();
+        assert_eq!(starts, [0, 20, 52, 60]);
+
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..200 {
+            let start = xorshift(&mut state) % 1000;
+            match xorshift(&mut state) % 3 {
+                0 => drop(coll.take_exact(1 + start % 7)),
+                _ => coll.insert(Interval::new(start, xorshift(&mut state) % 10)),
+            }
+            let free: Vec<_> = coll.iter_by_position().collect();
+            assert!(free.windows(2).all(|w| w[0].end() < w[1].start()));
+            assert_eq!(free.len(), coll.count());
+        }
+
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(10, 5));
+        coll.insert(Interval::new(0, 2));
+        let debug = format!("{:?}", coll);
+        assert!(debug.starts_with(
+            "IntervalsCollection { free: [Interval { start: 0, end: 2 }, Interval { start: 10, end: 15 }]"
+        ));
+    }
+
+    #[test]
+    fn drain() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(20, 30));
+        coll.insert(Interval::new(0, 5));
+        coll.insert(Interval::new(10, 2));
+        let drained: Vec<_> = coll.clone().drain().collect();
+        let expected = [
+            Interval::new(0, 5),
+            Interval::new(10, 2),
+            Interval::new(20, 30),
+        ];
+        assert_eq!(drained, expected);
+        let owned: Vec<_> = coll.clone().into_iter().collect();
+        assert_eq!(owned, expected);
+
+        let mut drain = coll.drain();
+        assert_eq!(drain.next(), Some(Interval::new(0, 5)));
+        drop(drain);
+        assert!(coll.is_empty());
+        assert_eq!(coll.take_enough(1), None);
+        coll.insert(Interval::new(7, 3));
+        assert_eq!(coll.drain().collect::<Vec<_>>(), [Interval::new(7, 3)]);
+    }
+
+    #[test]
+    fn retain() {
+        let mut coll = IntervalsCollection::default();
+        for i in 0..8 {
+            coll.insert(Interval::new(i * 100, i + 1));
+        }
+        let all = coll.clone();
+        assert_eq!(coll.retain(|_| true), 0);
+        assert_eq!(coll, all);
+
+        assert_eq!(coll.retain_at_least(6), 1 + 2 + 3 + 4 + 5);
+        let left: Vec<_> = coll.iter_by_position().copied().collect();
+        let expected = [
+            Interval::new(500, 6),
+            Interval::new(600, 7),
+            Interval::new(700, 8),
+        ];
+        assert_eq!(left, expected);
+        assert_eq!(coll.take_exact(1), Some(Interval::new(500, 1)));
+        coll.insert(Interval::new(500, 1));
+        coll.insert(Interval::new(606, 94));
+        assert_eq!(coll.iter().last(), Some(&Interval::from_bounds(600, 708)));
+
+        assert_eq!(coll.retain(|_| false), 6 + 108);
+        assert!(coll.is_empty());
+    }
+
+    #[test]
+    fn reject_insert() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_max_fragments(2, OverflowPolicy::RejectInsert);
+        assert_eq!(coll.insert_checked(Interval::new(0, 4)), Ok(()));
+        assert_eq!(coll.insert_checked(Interval::new(10, 4)), Ok(()));
+        let before = coll.clone();
+        let over = coll.insert_checked(Interval::new(20, 4));
+        assert_eq!(over, Err(InsertError::TooManyFragments));
+        assert_eq!(coll, before);
+
+        assert_eq!(coll.insert_checked(Interval::new(14, 2)), Ok(()));
+        assert_eq!(coll.insert_checked(Interval::new(4, 6)), Ok(()));
+        assert_eq!(coll.count(), 1);
+        assert_eq!(coll.insert_checked(Interval::new(20, 0)), Ok(()));
+
+        coll.insert(Interval::new(30, 1));
+        coll.insert(Interval::new(40, 1));
+        assert_eq!(coll.count(), 3);
+        assert_eq!(coll.take_at(Interval::new(2, 4)), Ok(Interval::new(2, 4)));
+        assert_eq!(coll.count(), 4);
+        assert_eq!(coll.dropped_len(), 0);
+    }
+
+    #[test]
+    fn drop_smallest() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_max_fragments(3, OverflowPolicy::DropSmallest);
+        for i in 0..5 {
+            coll.insert(Interval::new(i * 100, 10 - i));
+        }
+        assert_eq!(coll.count(), 3);
+        assert_eq!(coll.dropped_len(), 7 + 6);
+        let left: Vec<_> = coll.iter_by_position().copied().collect();
+        assert_eq!(
+            left,
+            [
+                Interval::new(0, 10),
+                Interval::new(100, 9),
+                Interval::new(200, 8)
+            ]
+        );
+
+        assert_eq!(
+            coll.take_at(Interval::new(102, 2)),
+            Ok(Interval::new(102, 2))
+        );
+        assert_eq!(coll.count(), 4);
+        assert_eq!(coll.dropped_len(), 7 + 6);
+        assert_eq!(coll.free_len(), 10 + 2 + 5 + 8);
+
+        assert_eq!(coll.take_multi(&[8, 10, 10]), Err(2));
+        assert_eq!(coll.count(), 4);
+        assert_eq!(coll.free_len(), 10 + 2 + 5 + 8);
+
+        coll.insert(Interval::new(300, 1));
+        assert_eq!(coll.count(), 3);
+        assert_eq!(coll.dropped_len(), 7 + 6 + 1 + 2);
+
+        coll.set_max_fragments(1, OverflowPolicy::DropSmallest);
+        assert_eq!(
+            coll.iter().copied().collect::<Vec<_>>(),
+            [Interval::new(0, 10)]
+        );
+        assert_eq!(coll.dropped_len(), 7 + 6 + 1 + 2 + 5 + 8);
+        coll.remove_max_fragments();
+        coll.insert(Interval::new(50, 1));
+        assert_eq!(coll.count(), 2);
+    }
+
+    #[test]
+    fn many_fragments() {
+        const COUNT: u64 = 100_000;
+        let mut coll: IntervalsCollection = (0..COUNT).map(|i| Interval::new(i * 4, 2)).collect();
+        assert_eq!(coll.count(), COUNT as usize);
+        assert_eq!(
+            coll.simulate_insert(Interval::new(2, 2)),
+            Interval::new(0, 6)
+        );
+
+        coll.insert(Interval::new(1, 8));
+        assert_eq!(coll.count(), COUNT as usize - 2);
+        assert_eq!(coll.iter_by_position().next(), Some(&Interval::new(0, 10)));
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..COUNT {
+            let i = xorshift(&mut state) % COUNT;
+            coll.insert(Interval::new(i * 4 + 2, 2));
+        }
+        for i in 0..COUNT {
+            coll.insert(Interval::new(i * 4 + 2, 2));
+        }
+        let all: Vec<_> = coll.iter().copied().collect();
+        assert_eq!(all, [Interval::new(0, COUNT * 4)]);
+    }
+
+    /// Block of `take_exact_aligned` for `BestFit`, found by full scan: the shortest
+    /// fitting one, then the one with the smallest pad, then the lowest.
+    fn best_aligned_fit(coll: &IntervalsCollection, length: u64, align: u64) -> Option<Interval> {
+        let fitting = coll.iter().filter(|i| {
+            i.align_start_up(align)
+                .is_some_and(|aligned| aligned.len() >= length)
+        });
+        let pad = |i: &Interval| interval::align_up(i.start(), align).unwrap() - i.start();
+        fitting
+            .min_by_key(|i| (i.len(), pad(i), i.start()))
+            .copied()
+    }
+
+    #[test]
+    fn aligned_search_skips_misaligned() {
+        let mut coll = IntervalsCollection::default();
+        for i in 0..5000 {
+            coll.insert(Interval::new(i * 32 + 1, 12));
+        }
+        coll.insert(Interval::new(1 << 20, 1 << 20));
+        for i in 0..1000 {
+            let block = best_aligned_fit(&coll, 4, 16).unwrap();
+            let taken = match i % 2 {
+                0 => coll.take_exact_aligned(4, 16),
+                _ => coll
+                    .take_enough_aligned(4, 16)
+                    .map(|i| Interval::new(i.start(), 4)),
+            };
+            let taken = taken.unwrap();
+            assert!(block.contains_interval(&taken));
+            if i % 2 == 1 {
+                coll.insert(Interval::from_bounds(taken.end(), block.end()));
+            }
+        }
+        assert_eq!(coll.free_len(), 5000 * 12 + (1 << 20) - 1000 * 4);
+
+        coll.insert(Interval::new(16 * 3, 6));
+        assert_eq!(coll.take_exact_aligned(4, 16), Some(Interval::new(48, 4)));
+        coll.insert(Interval::new(3 * 32 + 13, 7));
+        assert_eq!(coll.take_exact_aligned(4, 16), Some(Interval::new(112, 4)));
+    }
+
+    #[test]
+    fn aligned_search_matches_scan() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 1 << 16));
+        let mut taken = Vec::new();
+        for _ in 0..3000 {
+            let op = xorshift(&mut state) % 4;
+            if op == 0 && !taken.is_empty() {
+                let index = (xorshift(&mut state) % taken.len() as u64) as usize;
+                coll.insert(taken.swap_remove(index));
+                continue;
+            }
+            let length = 1 + xorshift(&mut state) % 48;
+            let align = [1, 4, 16, 64][(xorshift(&mut state) % 4) as usize];
+            let block = best_aligned_fit(&coll, length, align);
+            let int = coll.take_exact_aligned(length, align);
+            assert_eq!(int.is_some(), block.is_some());
+            if let (Some(int), Some(block)) = (int, block) {
+                assert!(block.contains_interval(&int));
+                assert_eq!(int.start() % align, 0);
+                taken.push(int);
+            }
+        }
+    }
+
+    #[test]
+    fn prefer_aligned_block() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(4, 24));
+        coll.insert(Interval::new(64, 24));
+        assert_eq!(coll.take_exact_aligned(8, 16), Some(Interval::new(64, 8)));
+        assert_eq!(coll.padding_created(), 0);
+        assert_eq!(coll.count(), 2);
+
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(4, 24));
+        coll.insert(Interval::new(40, 24));
+        assert_eq!(coll.take_exact_aligned(8, 16), Some(Interval::new(48, 8)));
+        assert_eq!(coll.padding_created(), 8);
+
+        let mut back = IntervalsCollection::default();
+        back.set_placement(SplitPlacement::Back);
+        back.insert(Interval::new(0, 20));
+        back.insert(Interval::new(36, 20));
+        assert_eq!(back.take_exact_aligned(8, 8), Some(Interval::new(48, 8)));
+        assert_eq!(back.take_exact_aligned(8, 8), Some(Interval::new(40, 8)));
+        assert_eq!(back.padding_created(), 0);
+    }
+
+    #[test]
+    fn aligned_workload_fragments() {
+        let mut coll = IntervalsCollection::default();
+        for i in 0..32 {
+            let pad = match i % 2 {
+                0 => 3,
+                _ => 0,
+            };
+            coll.insert(Interval::new(i * 256 + pad, 64));
+        }
+        // Picking misaligned blocks would leave 16 pads and 16 tails of 3 integers.
+        for _ in 0..16 {
+            let int = coll.take_exact_aligned(32, 32).unwrap();
+            assert!(int.start() % 512 >= 256);
+        }
+        assert_eq!(coll.padding_created(), 0);
+        assert_eq!(coll.count(), 24);
+        assert_eq!(coll.free_len(), 24 * 64);
+    }
+
+    #[test]
+    fn min_remainder() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(2);
+        coll.insert(Interval::new(0, 4097));
+        coll.insert(Interval::new(5000, 4098));
+        assert_eq!(coll.take_exact(4096), Some(Interval::new(0, 4097)));
+        assert_eq!(coll.take_exact(4096), Some(Interval::new(5000, 4096)));
+        assert_eq!(coll.free_len(), 2);
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(16);
+        coll.insert(Interval::new(1, 99));
+        assert_eq!(coll.take_exact_aligned(16, 16), Some(Interval::new(1, 31)));
+        assert_eq!(coll.take_exact_aligned(16, 16), Some(Interval::new(32, 16)));
+        assert_eq!(coll.take_exact_aligned(37, 4), Some(Interval::new(48, 52)));
+        assert_eq!(coll.padding_created(), 0);
+        assert!(coll.is_empty());
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(8);
+        coll.insert(Interval::new(3, 40));
+        assert_eq!(coll.take_enough_aligned(1, 16), Some(Interval::new(16, 27)));
+        assert_eq!(coll.padding_created(), 13);
+        assert_eq!(coll.take_exact_end_aligned(4, 8), Some(Interval::new(3, 5)));
+        assert_eq!(coll.free_len(), 8);
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(3);
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+        assert_eq!(coll.take_exact_no_cross(8, 1, 8), Some(Interval::new(0, 8)));
+        assert_eq!(
+            coll.take_exact_no_cross(8, 1, 16),
+            Some(Interval::new(20, 10))
+        );
+        assert_eq!(coll.free_len(), 2);
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(3);
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+        let excluded = [Interval::new(9, 1)];
+        assert_eq!(
+            coll.take_exact_excluding(8, 1, &excluded),
+            Some(Interval::new(0, 8))
+        );
+        assert_eq!(
+            coll.take_exact_excluding(8, 1, &excluded),
+            Some(Interval::new(20, 10))
+        );
+        assert_eq!(coll.free_len(), 2);
+    }
+
+    #[test]
+    fn min_remainder_conserves_space() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let mut plain = IntervalsCollection::default();
+        let mut zero = IntervalsCollection::default();
+        zero.set_min_remainder(0);
+        let mut rounded = IntervalsCollection::default();
+        rounded.set_min_remainder(8);
+        for coll in [&mut plain, &mut zero, &mut rounded] {
+            coll.insert(Interval::new(0, 1 << 12));
+        }
+        let (mut taken, mut rounded_taken) = (Vec::new(), Vec::new());
+        for _ in 0..1000 {
+            if xorshift(&mut state) % 3 == 0 && !taken.is_empty() {
+                let index = (xorshift(&mut state) % taken.len() as u64) as usize;
+                let int = taken.swap_remove(index);
+                plain.insert(int);
+                zero.insert(int);
+                if !rounded_taken.is_empty() {
+                    let index = index % rounded_taken.len();
+                    rounded.insert(rounded_taken.swap_remove(index));
+                }
+                continue;
+            }
+            let length = 1 + xorshift(&mut state) % 40;
+            let align = [1, 8, 32][(xorshift(&mut state) % 3) as usize];
+            let int = plain.take_exact_aligned(length, align);
+            assert_eq!(zero.take_exact_aligned(length, align), int);
+            if let Some(int) = int {
+                assert_eq!(int.len(), length);
+                taken.push(int);
+            }
+            if let Some(int) = rounded.take_exact_aligned(length, align) {
+                assert!(int.len() >= length);
+                rounded_taken.push(int);
+            }
+            assert_eq!(format!("{:?}", zero), format!("{:?}", plain));
+            let used: u64 = rounded_taken.iter().map(|i| i.len()).sum();
+            assert_eq!(rounded.free_len() + used, 1 << 12);
+        }
+    }
+
+    #[test]
+    fn gaps() {
+        let mut coll = IntervalsCollection::default();
+        let gaps = |coll: &IntervalsCollection, start, len| {
+            let found: Vec<_> = coll.gaps(Interval::new(start, len)).collect();
+            found
+        };
+        assert_eq!(gaps(&coll, 0, 100), [Interval::new(0, 100)]);
+        assert_eq!(gaps(&coll, 5, 0), []);
+
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+        coll.insert(Interval::new(40, 10));
+        assert_eq!(
+            gaps(&coll, 0, 50),
+            [Interval::new(10, 10), Interval::new(30, 10)]
+        );
+        assert_eq!(
+            gaps(&coll, 5, 100),
+            [
+                Interval::new(10, 10),
+                Interval::new(30, 10),
+                Interval::new(50, 55)
+            ]
+        );
+        assert_eq!(gaps(&coll, 22, 5), []);
+        assert_eq!(gaps(&coll, 12, 5), [Interval::new(12, 5)]);
+        assert_eq!(gaps(&coll, 25, 10), [Interval::new(30, 5)]);
+
+        let bounds = Interval::new(0, 60);
+        let mut tiles: Vec<_> = coll.intersecting(bounds).chain(coll.gaps(bounds)).collect();
+        tiles.sort_by_key(|i| i.start());
+        let total: u64 = tiles.iter().map(|i| i.len()).sum();
+        assert_eq!(total, 60);
+        assert!(tiles.windows(2).all(|w| w[0].end() == w[1].start()));
+    }
+
+    #[test]
+    fn intersecting() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+        coll.insert(Interval::new(40, 10));
+        let query = |start, len| {
+            let found: Vec<_> = coll.intersecting(Interval::new(start, len)).collect();
+            found
+        };
+
+        assert_eq!(query(2, 5), [Interval::new(2, 5)]);
+        assert_eq!(
+            query(5, 40),
+            [
+                Interval::new(5, 5),
+                Interval::new(20, 10),
+                Interval::new(40, 5)
+            ]
+        );
+        assert_eq!(query(10, 10), []);
+        assert_eq!(query(30, 10), []);
+        assert_eq!(query(25, 0), []);
+        assert_eq!(query(0, u64::MAX).len(), 3);
+        assert_eq!(query(49, 100), [Interval::new(49, 1)]);
+    }
+
+    #[test]
+    fn insert_checked() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(10, 10));
+        let double = coll.insert_checked(Interval::new(10, 10));
+        assert_eq!(double, Err(InsertError::Overlaps(Interval::new(10, 10))));
+        let partial = coll.insert_checked(Interval::new(15, 10));
+        assert_eq!(partial, Err(InsertError::Overlaps(Interval::new(10, 10))));
+        let covering = coll.insert_checked(Interval::new(0, 30));
+        assert_eq!(covering, Err(InsertError::Overlaps(Interval::new(10, 10))));
+        assert_eq!(coll.free_len(), 10);
+
+        assert_eq!(coll.insert_checked(Interval::new(20, 5)), Ok(()));
+        assert_eq!(coll.insert_checked(Interval::new(5, 5)), Ok(()));
+        assert_eq!(coll.insert_checked(Interval::new(12, 0)), Ok(()));
+        let free: Vec<_> = coll.iter_by_position().copied().collect();
+        assert_eq!(free, [Interval::new(5, 20)]);
+    }
+
+    #[test]
+    fn remove() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 20));
+        assert_eq!(coll.remove(Interval::new(5, 5)), 5);
+        let free: Vec<_> = coll.iter_by_position().copied().collect();
+        assert_eq!(free, [Interval::new(0, 5), Interval::new(10, 10)]);
+
+        coll.insert(Interval::new(30, 10));
+        assert_eq!(coll.remove(Interval::new(2, 36)), 3 + 10 + 8);
+        let free: Vec<_> = coll.iter_by_position().copied().collect();
+        assert_eq!(free, [Interval::new(0, 2), Interval::new(38, 2)]);
+
+        assert_eq!(coll.remove(Interval::new(10, 20)), 0);
+        assert_eq!(coll.remove(Interval::new(0, 0)), 0);
+        assert_eq!(coll.remove(Interval::new(0, 100)), 4);
+        assert!(coll.is_empty());
+
+        coll.insert(Interval::new(0, 10));
+        coll.remove(Interval::new(3, 2));
+        coll.insert(Interval::new(3, 2));
+        assert_eq!(
+            coll.iter().copied().collect::<Vec<_>>(),
+            [Interval::new(0, 10)]
+        );
+    }
+
+    #[test]
+    fn find_fit_matches_take() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let strategies = [
+            Strategy::BestFit,
+            Strategy::FirstFit,
+            Strategy::WorstFit,
+            Strategy::NextFit,
+            Strategy::Random,
+        ];
+        for strategy in strategies.iter() {
+            let mut coll = IntervalsCollection::default();
+            coll.set_strategy(*strategy);
+            coll.insert(Interval::new(0, 1024));
+            let mut taken = Vec::new();
+            for _ in 0..500 {
+                let rnd = xorshift(&mut state);
+                if rnd % 4 == 0 && !taken.is_empty() {
+                    coll.insert(taken.swap_remove((rnd >> 8) as usize % taken.len()));
+                    continue;
+                }
+                if rnd % 7 == 0 {
+                    coll.set_placement(SplitPlacement::Back);
+                }
+                let length = (rnd >> 8) % 64;
+                let align = [0, 1, 3, 8, 24, 64][(rnd >> 20) as usize % 6];
+                coll.set_min_remainder([0, 0, 3, 16][(rnd >> 28) as usize % 4]);
+                let fit = coll.find_fit(length, align);
+                assert_eq!(coll.can_allocate(length, align), fit.is_some());
+                let int = coll.take_exact_aligned(length, align);
+                assert_eq!(fit, int);
+                taken.extend(int);
+            }
+        }
+
+        let mut coll = IntervalsCollection::default();
+        coll.set_min_remainder(3);
+        coll.insert(Interval::new(0, 10));
+        assert_eq!(coll.find_fit(8, 1), Some(Interval::new(0, 10)));
+        assert_eq!(coll.take_exact_aligned(8, 1), Some(Interval::new(0, 10)));
+    }
+
+    #[test]
+    fn take_multi() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 64));
+        let taken = coll.take_multi(&[4, 8, 2]).unwrap();
+        assert_eq!(
+            taken,
+            [
+                Interval::new(0, 4),
+                Interval::new(4, 8),
+                Interval::new(12, 2)
+            ]
+        );
+        assert_eq!(coll.take_multi(&[]), Ok(Vec::new()));
+        assert_eq!(coll.take_multi(&[10, 0, 10]), Err(1));
+
+        let aligned = coll.take_multi_aligned(&[(3, 8), (5, 16)]).unwrap();
+        assert_eq!(aligned, [Interval::new(16, 3), Interval::new(32, 5)]);
+        assert_eq!(coll.free_len(), 64 - 14 - 8);
     }
 
-    pub fn take_enough_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
-        let int_len_ord = IntervalLenOrd(Interval::new(0, length));
-        let bounds = (Bound::Included(int_len_ord), Bound::Unbounded);
-        let mut range = self.btree.range(bounds);
-        let enough_int = range
-            .find(|i| {
-                let pad = Self::align_pad(&i.0, align);
-                i.0.len() >= length + pad
-            })
-            .copied();
-        if let Some(i) = enough_int {
-            self.btree.remove(&i);
-            return Some(i.0);
+    #[test]
+    fn take_multi_rollback() {
+        for strategy in [Strategy::BestFit, Strategy::NextFit, Strategy::Random].iter() {
+            let mut coll = IntervalsCollection::default();
+            coll.set_strategy(*strategy);
+            for k in 0..8 {
+                coll.insert(Interval::new(k * 100, 50 + k));
+            }
+            coll.take_exact(7).unwrap();
+            let before = format!("{:?}", coll);
+            let bounds = coll.aligned_bounds;
+
+            let mut requests: Vec<_> = (1..40).map(|k| (k % 9 + 1, 1 << (k % 4))).collect();
+            requests.push((60, 1));
+            assert_eq!(coll.take_multi_aligned(&requests), Err(39));
+            assert_eq!(format!("{:?}", coll), before);
+            assert_eq!(coll.aligned_bounds, bounds);
+
+            let lengths: Vec<_> = requests.iter().map(|(length, _)| *length).collect();
+            assert_eq!(coll.take_multi(&lengths), Err(39));
+            assert_eq!(format!("{:?}", coll), before);
         }
-        None
     }
 
-    pub fn take_exact(&mut self, length: u64) -> Option<Interval> {
-        let enough_free_interval = self.take_enough(length);
-        enough_free_interval.map(|int| {
-            if int.len() > length {
-                let (req, extra) = int.split(length);
-                self.btree.insert(IntervalLenOrd(extra));
-                return req;
-            }
-            int
-        })
+    #[test]
+    fn take_scatter() {
+        let mut coll = IntervalsCollection::default();
+        for k in 0..4 {
+            coll.insert(Interval::new(k * 20, 10 - k));
+        }
+        assert_eq!(coll.take_exact(20), None);
+        let before = format!("{:?}", coll);
+        assert_eq!(coll.take_scatter(35, 1), None);
+        assert_eq!(coll.take_scatter(20, 8), None);
+        assert_eq!(coll.take_scatter(25, 9), None);
+        assert_eq!(coll.take_scatter(0, 1), None);
+        assert_eq!(format!("{:?}", coll), before);
+
+        let pieces = coll.take_scatter(20, 1).unwrap();
+        assert_eq!(
+            pieces,
+            [
+                Interval::new(0, 10),
+                Interval::new(20, 9),
+                Interval::new(40, 1)
+            ]
+        );
+        assert_eq!(coll.free_len(), 34 - 20);
+
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 10));
+        coll.insert(Interval::new(40, 10));
+        let pieces = coll.take_scatter(21, 5).unwrap();
+        assert_eq!(pieces.len(), 3);
+        assert!(pieces.iter().all(|p| p.len() >= 5));
+        assert_eq!(pieces.iter().map(|p| p.len()).sum::<u64>(), 21);
+        assert_eq!(coll.free_len(), 9);
     }
 
-    pub fn take_exact_aligned(&mut self, length: u64, align: u64) -> Option<Interval> {
-        let enough_free_interval = self.take_enough_aligned(length, align);
-        enough_free_interval.map(|int| {
-            let align_pad = Self::align_pad(&int, align);
-            if align_pad > 0 {
-                let pad_int = Interval::new(int.start(), align_pad);
-                self.btree.insert(IntervalLenOrd(pad_int));
+    #[test]
+    fn from_iter() {
+        let input = [
+            Interval::new(10, 5),
+            Interval::new(0, 4),
+            Interval::new(12, 10),
+            Interval::new(4, 2),
+            Interval::new(30, 0),
+            Interval::new(40, 3),
+        ];
+        let mut inserted = IntervalsCollection::default();
+        for int in input.iter() {
+            inserted.insert(*int);
+        }
+        let collected: IntervalsCollection = input.iter().copied().collect();
+        assert_eq!(format!("{:?}", collected), format!("{:?}", inserted));
+
+        let mut extended = IntervalsCollection::default();
+        extended.insert(Interval::new(6, 4));
+        extended.extend(input.iter().copied());
+        let free: Vec<_> = extended.iter_by_position().copied().collect();
+        assert_eq!(free, [Interval::new(0, 22), Interval::new(40, 3)]);
+
+        let sorted = [
+            Interval::new(0, 4),
+            Interval::new(4, 2),
+            Interval::new(10, 12),
+            Interval::new(30, 0),
+            Interval::new(40, 3),
+        ];
+        let mut fast = IntervalsCollection::from_sorted_disjoint(sorted.iter().copied());
+        let slow: IntervalsCollection = sorted.iter().copied().collect();
+        assert_eq!(format!("{:?}", fast), format!("{:?}", slow));
+        assert_eq!(fast.take_exact(12), Some(Interval::new(10, 12)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn from_sorted_disjoint_unsorted() {
+        let input = [Interval::new(10, 5), Interval::new(0, 4)];
+        IntervalsCollection::from_sorted_disjoint(input.iter().copied());
+    }
+
+    #[test]
+    fn eq() {
+        let pieces: Vec<_> = (0..10).map(|k| Interval::new(k * 3, 3)).collect();
+        let forward: IntervalsCollection = pieces.iter().copied().collect();
+        let backward: IntervalsCollection = pieces.iter().rev().copied().collect();
+        let sorted = IntervalsCollection::from_sorted_disjoint(pieces.iter().copied());
+        let mut whole = IntervalsCollection::default();
+        whole.insert(Interval::new(0, 30));
+        assert_eq!(forward, backward);
+        assert_eq!(forward, sorted);
+        assert_eq!(forward, whole);
+
+        let mut round_trip = whole.clone();
+        round_trip.set_strategy(Strategy::Random);
+        let a = round_trip.take_exact(7).unwrap();
+        let b = round_trip.take_exact_aligned(5, 8).unwrap();
+        assert_ne!(round_trip, whole);
+        round_trip.insert(a);
+        round_trip.insert(b);
+        assert_eq!(round_trip, whole);
+
+        let mut other = whole.clone();
+        other.remove(Interval::new(29, 1));
+        assert_ne!(other, whole);
+        assert_ne!(IntervalsCollection::default(), whole);
+    }
+
+    #[test]
+    fn take_where() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(250, 20));
+        coll.insert(Interval::new(0, 30));
+        coll.insert(Interval::new(100, 40));
+        let before = format!("{:?}", coll);
+        assert_eq!(coll.take_enough_where(5, |_| false), None);
+        assert_eq!(coll.take_exact_where(5, |_| false), None);
+        assert_eq!(format!("{:?}", coll), before);
+
+        let no_straddle = |i: &Interval| i.start() / 256 == (i.end() - 1) / 256;
+        let int = coll.take_enough_where(10, no_straddle);
+        assert_eq!(int, Some(Interval::new(0, 30)));
+        let int = coll.take_exact_where(10, no_straddle);
+        assert_eq!(int, Some(Interval::new(100, 10)));
+        assert_eq!(coll.take_exact_where(50, |_| true), None);
+        assert_eq!(coll.take_exact(10), Some(Interval::new(250, 10)));
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::NextFit);
+        coll.insert(Interval::new(0, 100));
+        let a = coll.take_exact(10).unwrap();
+        coll.take_exact_aligned(10, 16).unwrap();
+        let snapshot = coll.snapshot();
+        let saved = format!("{:?}", coll);
+
+        coll.insert(a);
+        coll.take_exact(30).unwrap();
+        coll.set_strategy(Strategy::BestFit);
+        coll.restore(&snapshot);
+        assert_eq!(format!("{:?}", coll), saved);
+
+        let next = coll.take_exact(5);
+        coll.take_multi(&[3, 3, 3]).unwrap();
+        coll.restore(&snapshot);
+        assert_eq!(format!("{:?}", coll), saved);
+        assert_eq!(coll.take_exact(5), next);
+    }
+
+    #[test]
+    fn display() {
+        let mut coll = IntervalsCollection::default();
+        assert_eq!(coll.to_string(), "free: (0 blocks, 0 total)");
+        coll.insert(Interval::from_bounds(4096, 8192));
+        coll.insert(Interval::from_bounds(0, 16));
+        coll.insert(Interval::from_bounds(64, 128));
+        assert_eq!(
+            coll.to_string(),
+            "free: [0..16) [64..128) [4096..8192) (3 blocks, 4176 total)"
+        );
+        assert_eq!(
+            format!("{:#}", coll),
+            "free: (3 blocks, 4176 total)\n  [0..16)\n  [64..128)\n  [4096..8192)"
+        );
+    }
+
+    #[test]
+    fn point_navigation() {
+        let mut coll = IntervalsCollection::default();
+        assert_eq!(coll.containing_free_block(0), None);
+        assert_eq!(coll.next_free_at_or_after(0), None);
+        assert_eq!(coll.prev_free_before(u64::MAX), None);
+
+        let (a, b) = (Interval::new(10, 10), Interval::new(30, 5));
+        coll.insert(a);
+        coll.insert(b);
+        assert_eq!(coll.containing_free_block(10), Some(&a));
+        assert_eq!(coll.containing_free_block(19), Some(&a));
+        assert_eq!(coll.containing_free_block(20), None);
+        assert_eq!(coll.containing_free_block(5), None);
+        assert_eq!(coll.containing_free_block(40), None);
+
+        assert_eq!(coll.next_free_at_or_after(0), Some(&a));
+        assert_eq!(coll.next_free_at_or_after(15), Some(&a));
+        assert_eq!(coll.next_free_at_or_after(20), Some(&b));
+        assert_eq!(coll.next_free_at_or_after(34), Some(&b));
+        assert_eq!(coll.next_free_at_or_after(35), None);
+
+        assert_eq!(coll.prev_free_before(10), None);
+        assert_eq!(coll.prev_free_before(11), Some(&a));
+        assert_eq!(coll.prev_free_before(25), Some(&a));
+        assert_eq!(coll.prev_free_before(30), Some(&a));
+        assert_eq!(coll.prev_free_before(31), Some(&b));
+        assert_eq!(coll.prev_free_before(u64::MAX), Some(&b));
+    }
+
+    #[test]
+    fn strategies_choose_different_blocks() {
+        let script = |strategy| {
+            let mut coll = IntervalsCollection::default();
+            coll.set_strategy(strategy);
+            coll.insert(Interval::new(0, 8));
+            coll.insert(Interval::new(20, 4));
+            coll.insert(Interval::new(40, 16));
+            coll.insert(Interval::new(70, 8));
+            let first = coll.take_exact(4).unwrap().start();
+            let second = coll.take_exact(4).unwrap().start();
+            coll.insert(Interval::new(first, 4));
+            let aligned = coll.take_exact_aligned(2, 8).unwrap().start();
+            let enough = coll.take_enough(3).unwrap();
+            (first, second, aligned, enough)
+        };
+
+        let best = (20, 0, 72, Interval::new(4, 4));
+        assert_eq!(script(Strategy::BestFit), best);
+        let first = (0, 4, 0, Interval::new(20, 4));
+        assert_eq!(script(Strategy::FirstFit), first);
+        let worst = (40, 44, 0, Interval::new(48, 8));
+        assert_eq!(script(Strategy::WorstFit), worst);
+        let next = (0, 4, 40, Interval::new(42, 14));
+        assert_eq!(script(Strategy::NextFit), next);
+    }
+
+    #[test]
+    fn take_enough_aligned_keeps_padding() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(3, 20));
+        let int = coll.take_enough_aligned(4, 8).unwrap();
+        assert_eq!(int, Interval::from_bounds(8, 23));
+        assert_eq!(
+            coll.iter().copied().collect::<Vec<_>>(),
+            [Interval::new(3, 5)]
+        );
+        assert_eq!(coll.padding_created(), 5);
+        assert_eq!(coll.take_enough_aligned(1, 8), None);
+
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..200 {
+            let mut coll = IntervalsCollection::default();
+            let mut total = 0;
+            for _ in 0..4 {
+                let int = Interval::new(xorshift(&mut state) % 256, xorshift(&mut state) % 32);
+                total += int.len() - coll.free_within(int);
+                coll.insert(int);
             }
+            let length = xorshift(&mut state) % 16;
+            let align = 1 << (xorshift(&mut state) % 5);
+            let free = |coll: &IntervalsCollection| coll.iter().map(|i| i.len()).sum::<u64>();
+            if let Some(int) = coll.take_enough_aligned(length, align) {
+                assert!(int.start() % align == 0);
+                assert!(int.len() >= length);
+                assert_eq!(coll.coverage(&int), Coverage::None);
+                assert_eq!(free(&coll) + int.len(), total);
+            } else {
+                assert_eq!(free(&coll), total);
+            }
+        }
+    }
+
+    #[test]
+    fn unusual_aligns() {
+        let fresh = || {
+            let mut coll = IntervalsCollection::default();
+            coll.insert(Interval::new(5, 100));
+            coll
+        };
+        for align in [0, 1].iter() {
+            assert_eq!(
+                fresh().take_exact_aligned(10, *align),
+                Some(Interval::new(5, 10))
+            );
+            let int = fresh().take_enough_aligned(10, *align);
+            assert_eq!(int, Some(Interval::new(5, 100)));
+        }
+        assert_eq!(
+            fresh().take_exact_aligned(10, 3),
+            Some(Interval::new(6, 10))
+        );
+        assert_eq!(
+            fresh().take_exact_aligned(10, 24),
+            Some(Interval::new(24, 10))
+        );
+        assert_eq!(
+            fresh().take_enough_aligned(10, 24),
+            Some(Interval::from_bounds(24, 105))
+        );
+        assert_eq!(fresh().take_exact_aligned(10, 96), None);
+        assert_eq!(
+            fresh().take_exact_aligned(9, 96),
+            Some(Interval::new(96, 9))
+        );
+        assert_eq!(fresh().take_exact_aligned(1, 1 << 40), None);
+        assert_eq!(fresh().take_enough_aligned(1, u64::MAX), None);
+
+        let mut coll = fresh();
+        coll.set_strategy(Strategy::Random);
+        for align in [0, 1, 3, 24].iter() {
+            let int = coll.take_exact_aligned(4, *align).unwrap();
+            assert!(int.start() % std::cmp::max(*align, 1) == 0);
+        }
+        coll.set_placement(SplitPlacement::Back);
+        coll.set_strategy(Strategy::BestFit);
+        let int = coll.take_exact_aligned(4, 24).unwrap();
+        assert!(int.start() % 24 == 0);
+    }
+
+    #[test]
+    fn free_len() {
+        let mut coll = IntervalsCollection::default();
+        assert!(coll.is_empty());
+        assert_eq!((coll.free_len(), coll.count()), (0, 0));
 
-            let int = Interval::new(int.start() + align_pad, int.len() - align_pad);
-            if int.len() > length {
-                let (req, extra) = int.split(length);
-                self.btree.insert(IntervalLenOrd(extra));
-                return req;
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 5));
+        coll.insert(Interval::new(10, 3));
+        assert_eq!((coll.free_len(), coll.count()), (18, 2));
+        coll.take_exact(4).unwrap();
+        assert_eq!((coll.free_len(), coll.count()), (14, 2));
+
+        coll.reset(Interval::new(0, u64::MAX));
+        assert_eq!((coll.free_len(), coll.count()), (u64::MAX, 1));
+        assert!(!coll.is_empty());
+        coll.take_max().unwrap();
+        assert!(coll.is_empty());
+
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        coll.insert(Interval::new(0, 1 << 10));
+        for _ in 0..500 {
+            let start = xorshift(&mut state) % (1 << 10);
+            let len = xorshift(&mut state) % 32;
+            match xorshift(&mut state) % 4 {
+                0 => coll.insert(Interval::new(start, len)),
+                1 => {
+                    coll.remove(Interval::new(start, len));
+                }
+                2 => {
+                    coll.take_exact_aligned(len, 8);
+                }
+                _ => {
+                    coll.take_enough(len);
+                }
             }
-            int
-        })
+            let sum: u64 = coll.iter().map(|i| i.len()).sum();
+            assert_eq!(coll.free_len(), sum);
+        }
     }
 
-    pub fn insert(&mut self, interval: Interval) {
-        let near_intervals = self.near(&interval);
-        let mut connection = interval;
-        for int in &near_intervals {
-            self.btree.remove(int);
-            connection = connection.connect(&int.0);
+    #[test]
+    fn largest_smallest_free() {
+        let mut coll = IntervalsCollection::default();
+        assert_eq!((coll.largest_free(), coll.smallest_free()), (None, None));
+
+        coll.insert(Interval::new(3, 30));
+        coll.take_exact_aligned(10, 16).unwrap();
+        assert_eq!(coll.largest_free(), Some(Interval::new(3, 13)));
+        assert_eq!(coll.smallest_free(), Some(Interval::new(26, 7)));
+        coll.take_exact_aligned(4, 8).unwrap();
+        assert_eq!(coll.largest_free(), Some(Interval::new(26, 7)));
+        assert_eq!(coll.smallest_free(), Some(Interval::new(12, 4)));
+        coll.insert(Interval::new(40, 4));
+        assert_eq!(coll.largest_free(), Some(Interval::new(26, 7)));
+        assert_eq!(coll.smallest_free(), Some(Interval::new(12, 4)));
+        assert_eq!(coll.iter().count(), 4);
+    }
+
+    #[test]
+    fn fragmentation() {
+        let mut coll = IntervalsCollection::default();
+        assert_eq!(coll.fragmentation(), 0.0);
+        assert_eq!(coll.external_fragmentation_for(4), 0.0);
+        coll.insert(Interval::new(0, 16));
+        assert_eq!(coll.fragmentation(), 0.0);
+        assert_eq!(coll.external_fragmentation_for(16), 0.0);
+        assert_eq!(coll.external_fragmentation_for(17), 1.0);
+
+        let mut coll = IntervalsCollection::default();
+        for hole in 0..4 {
+            coll.insert(Interval::new(hole * 2, 1));
         }
-        self.btree.insert(IntervalLenOrd(connection));
+        coll.insert(Interval::new(10, 4));
+        assert_eq!(coll.fragmentation(), 0.5);
+        assert_eq!(coll.external_fragmentation_for(1), 0.0);
+        assert_eq!(coll.external_fragmentation_for(2), 0.5);
+        assert_eq!(coll.external_fragmentation_for(5), 1.0);
+
+        coll.insert(Interval::new(20, 8));
+        assert_eq!(coll.fragmentation(), 0.5);
+        assert_eq!(coll.external_fragmentation_for(5), 0.5);
+        assert_eq!(coll.external_fragmentation_for(0), 0.0);
     }
 
-    fn near(&self, interval: &Interval) -> Vec<IntervalLenOrd> {
-        self.btree
-            .iter()
-            .filter(|int| interval.near(&int.0))
-            .cloned()
-            .collect()
+    #[test]
+    fn take_max() {
+        let mut coll = IntervalsCollection::default();
+        assert_eq!(coll.peek_max(), None);
+        assert_eq!(coll.take_max(), None);
+
+        coll.insert(Interval::new(30, 8));
+        coll.insert(Interval::new(0, 4));
+        coll.insert(Interval::new(10, 8));
+        assert_eq!(coll.peek_max(), Some(Interval::new(10, 8)));
+        assert_eq!(coll.take_max(), Some(Interval::new(10, 8)));
+        assert_eq!(coll.take_max(), Some(Interval::new(30, 8)));
+        assert_eq!(coll.peek_max(), Some(Interval::new(0, 4)));
+        assert_eq!(coll.take_max(), Some(Interval::new(0, 4)));
+        assert_eq!(coll.take_max(), None);
     }
 
-    fn align_pad(int: &Interval, align: u64) -> u64 {
-        let rem = int.start() % align;
-        if rem == 0 {
-            return 0;
+    #[test]
+    fn take_at() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(20, 5));
+
+        assert_eq!(coll.take_at(Interval::new(3, 4)), Ok(Interval::new(3, 4)));
+        assert_eq!(coll.take_at(Interval::new(20, 5)), Ok(Interval::new(20, 5)));
+        assert_eq!(coll.take_at(Interval::new(7, 0)), Ok(Interval::new(7, 0)));
+        let mut free: Vec<_> = coll.iter().copied().collect();
+        free.sort();
+        assert_eq!(free, [Interval::new(0, 3), Interval::new(7, 3)]);
+
+        assert_eq!(
+            coll.take_at(Interval::new(2, 2)),
+            Err(TakeAtError::PartiallyFree)
+        );
+        assert_eq!(
+            coll.take_at(Interval::new(8, 10)),
+            Err(TakeAtError::PartiallyFree)
+        );
+        assert_eq!(coll.take_at(Interval::new(3, 4)), Err(TakeAtError::NotFree));
+        assert_eq!(
+            coll.take_at(Interval::new(20, 1)),
+            Err(TakeAtError::NotFree)
+        );
+        assert_eq!(coll.iter().count(), 2);
+
+        coll.insert(Interval::new(3, 4));
+        assert_eq!(
+            coll.iter().copied().collect::<Vec<_>>(),
+            [Interval::new(0, 10)]
+        );
+    }
+
+    #[test]
+    fn clear_reset() {
+        let mut coll = test_data();
+        coll.set_strategy(Strategy::NextFit);
+        coll.take_exact(3).unwrap();
+        coll.clear();
+        assert_eq!(coll.take_enough(1), None);
+        assert_eq!(coll.cursor(), 0);
+
+        coll.reset(Interval::new(0, 10));
+        assert_eq!(coll.strategy(), Strategy::NextFit);
+        assert_eq!(coll.take_exact(10), Some(Interval::new(0, 10)));
+        assert_eq!(coll.take_enough(1), None);
+    }
+
+    #[test]
+    fn subranges_contract() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 10));
+        coll.insert(Interval::new(10, 6));
+        assert_eq!(
+            coll.iter().copied().collect::<Vec<_>>(),
+            [Interval::new(0, 16)]
+        );
+
+        assert_eq!(coll.take_exact(3), Some(Interval::new(0, 3)));
+        assert_eq!(coll.take_exact_aligned(4, 8), Some(Interval::new(8, 4)));
+        assert_eq!(coll.take_exact(20), None);
+        assert_eq!(coll.take_exact_aligned(4, 16), None);
+
+        let mut free: Vec<_> = coll.iter().copied().collect();
+        free.sort();
+        assert_eq!(free, [Interval::new(3, 5), Interval::new(12, 4)]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn free_intervals_alias() {
+        let mut coll = crate::collection::FreeIntervals::default();
+        coll.insert(Interval::new(0, 4));
+        let coll: IntervalsCollection = coll;
+        assert_eq!(coll.iter().count(), 1);
+    }
+
+    #[test]
+    fn const_new() {
+        const EMPTY: IntervalsCollection = IntervalsCollection::new();
+        let mut coll = EMPTY;
+        assert_eq!(coll.iter().count(), 0);
+        assert_eq!(coll.strategy(), Strategy::default());
+        assert_eq!(coll.placement(), SplitPlacement::default());
+        coll.insert(Interval::new(0, 10));
+        assert_eq!(coll.take_exact(4), Some(Interval::new(0, 4)));
+    }
+
+    #[test]
+    fn empty_intervals() {
+        let mut coll = test_data();
+        coll.insert(Interval::new(20, 0));
+        coll.insert(Interval::new(10, 0));
+        assert_eq!(coll.iter().collect::<Vec<_>>(), [&Interval::new(0, 10)]);
+
+        assert_eq!(coll.take_enough(0), None);
+        assert_eq!(coll.take_enough_aligned(0, 4), None);
+        assert_eq!(coll.take_exact(0), None);
+        assert_eq!(coll.take_exact_aligned(0, 4), None);
+        assert_eq!(coll.take_exact_end_aligned(0, 4), None);
+        assert_eq!(coll.take_exact_no_cross(0, 1, 4), None);
+        assert_eq!(coll.take_exact_excluding(0, 1, &[]), None);
+        assert!(coll.carve_at(Interval::new(5, 0)));
+        assert_eq!(coll.iter().collect::<Vec<_>>(), [&Interval::new(0, 10)]);
+
+        let strategies = [
+            Strategy::BestFit,
+            Strategy::FirstFit,
+            Strategy::WorstFit,
+            Strategy::NextFit,
+            Strategy::Random,
+        ];
+        for strategy in strategies.iter() {
+            coll.set_strategy(*strategy);
+            for length in [3, 1, 4, 2].iter() {
+                coll.take_exact_aligned(*length, 2);
+            }
+            assert!(coll.iter().all(|i| !i.is_empty()));
+            coll.insert(Interval::new(0, 10));
         }
-        align - rem
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Interval> {
-        self.btree.iter().map(|i| &i.0)
+    #[test]
+    fn take_exact_no_cross_top() {
+        let mut coll = IntervalsCollection::default();
+        let top = Interval::new(u64::MAX - 20, 20);
+        coll.insert(top);
+
+        let int = coll.take_exact_no_cross(10, 1, 16).unwrap();
+        assert_eq!(int, Interval::new(u64::MAX - 15, 10));
+        assert!(coll.take_exact_no_cross(6, 1, 1 << 63).is_none());
+        let int = coll.take_exact_no_cross(5, 1, 1 << 63).unwrap();
+        assert_eq!(int, Interval::new(u64::MAX - 20, 5));
+        let int = coll.take_exact_no_cross(5, 1, 1 << 63).unwrap();
+        assert_eq!(int.end(), u64::MAX);
     }
-}
 
-impl Default for IntervalsCollection {
-    fn default() -> Self {
-        let btree = BTreeSet::new();
-        Self { btree }
+    #[test]
+    fn take_exact_end_aligned() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(1, 40));
+
+        let int = coll.take_exact_end_aligned(5, 8).unwrap();
+        assert_eq!(int, Interval::new(3, 5));
+        assert_eq!(int.end() % 8, 0);
+        assert!(coll.take_exact_end_aligned(40, 8).is_none());
+
+        let mut it = coll.iter();
+        assert_eq!(*it.next().unwrap(), Interval::new(1, 2));
+        assert_eq!(*it.next().unwrap(), Interval::new(8, 33));
+        assert!(it.next().is_none());
     }
-}
 
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
-struct IntervalLenOrd(Interval);
+    #[test]
+    fn end_aligned_vs_start_aligned() {
+        let free = Interval::new(3, 60);
+        let start_aligned = |length, align| {
+            let mut coll = IntervalsCollection::default();
+            coll.insert(free);
+            coll.take_exact_aligned(length, align).unwrap()
+        };
+        let end_aligned = |length, align| {
+            let mut coll = IntervalsCollection::default();
+            coll.insert(free);
+            coll.take_exact_end_aligned(length, align).unwrap()
+        };
 
-impl PartialOrd for IntervalLenOrd {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        PartialOrd::partial_cmp(&self.0.len(), &other.0.len())
+        assert_eq!(start_aligned(16, 8), end_aligned(16, 8));
+        assert_eq!(start_aligned(24, 4), end_aligned(24, 4));
+
+        assert_eq!(start_aligned(10, 8), Interval::new(8, 10));
+        assert_eq!(end_aligned(10, 8), Interval::new(6, 10));
     }
-}
 
-impl Ord for IntervalLenOrd {
-    fn cmp(&self, other: &Self) -> Ordering {
-        Ord::cmp(&self.0.len(), &other.0.len())
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::collection::IntervalsCollection;
-    use crate::interval::Interval;
+    fn max_aligned_len(coll: &IntervalsCollection, align: u64) -> u64 {
+        coll.iter()
+            .map(|i| {
+                i.len()
+                    .saturating_sub(IntervalsCollection::align_pad(i, align))
+            })
+            .max()
+            .unwrap_or(0)
+    }
 
-    fn test_data() -> IntervalsCollection {
+    #[test]
+    fn aligned_bounds_never_underestimate() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
         let mut coll = IntervalsCollection::default();
-        let free_interval = Interval::new(0, 10);
-        coll.insert(free_interval);
-        coll
+        coll.insert(Interval::new(0, 4096));
+        let mut taken = Vec::new();
+
+        for _ in 0..5000 {
+            let rnd = xorshift(&mut state);
+            if rnd % 3 == 0 && !taken.is_empty() {
+                let idx = (rnd >> 8) as usize % taken.len();
+                coll.insert(taken.swap_remove(idx));
+            } else {
+                let length = 1 + (rnd >> 8) % 300;
+                let align = 1 << ((rnd >> 24) % 8);
+                match coll.take_exact_aligned(length, align) {
+                    Some(int) => taken.push(int),
+                    None => assert!(max_aligned_len(&coll, align) < length),
+                }
+            }
+
+            for (k, bound) in coll.aligned_bounds.iter().enumerate() {
+                if let Some(bound) = bound {
+                    assert!(max_aligned_len(&coll, 1 << k) <= *bound);
+                }
+            }
+            let mut by_len: Vec<_> = coll.iter().map(|i| (i.start(), i.end())).collect();
+            by_len.sort();
+            let by_start: Vec<_> = coll
+                .by_start
+                .values()
+                .map(|i| (i.start(), i.end()))
+                .collect();
+            assert_eq!(by_len, by_start);
+        }
     }
 
     #[test]
-    fn take_enough() {
-        let mut coll = test_data();
-        let seven_len = coll.take_enough(7).unwrap();
-        assert!(seven_len.len() > 7);
-        assert!(coll.take_enough(1).is_none());
+    fn aligned_bounds_fast_failure() {
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(1, 16));
+        assert!(coll.take_exact_aligned(16, 4).is_none());
+        assert_eq!(coll.aligned_bounds[2], Some(15));
+        assert!(coll.take_exact_aligned(14, 4).is_none());
+        assert_eq!(coll.aligned_bounds[2], Some(13));
+        assert!(coll.take_exact_aligned(20, 4).is_none());
+        assert_eq!(coll.aligned_bounds[2], Some(13));
+
+        coll.insert(Interval::new(17, 16));
+        assert_eq!(coll.aligned_bounds[2], None);
+        assert!(coll.take_exact_aligned(20, 4).is_some());
     }
 
     #[test]
-    fn insert_non_intersect() {
+    fn back_placement() {
         let mut coll = test_data();
+        coll.set_placement(SplitPlacement::Back);
         coll.insert(Interval::new(15, 5));
+        assert_eq!(coll.take_exact(3).unwrap(), Interval::new(17, 3));
+        assert_eq!(coll.take_exact(10).unwrap(), Interval::new(0, 10));
         let mut it = coll.iter();
-        assert_eq!(it.next().unwrap().len(), 5);
-        assert_eq!(it.next().unwrap().len(), 10);
+        assert_eq!(*it.next().unwrap(), Interval::new(15, 2));
         assert!(it.next().is_none());
     }
 
     #[test]
-    fn insert_intersect() {
-        let mut coll = test_data();
-        coll.insert(Interval::new(15, 5));
-        coll.insert(Interval::new(8, 10));
+    fn back_placement_aligned() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_placement(SplitPlacement::Back);
+        coll.insert(Interval::new(1, 30));
+
+        // Start is aligned down from `end - length`, the pad is left after the result.
+        let int = coll.take_exact_aligned(5, 4).unwrap();
+        assert_eq!(int, Interval::new(24, 5));
+        assert_eq!(coll.padding_created(), 2);
+        let int = coll.take_exact_aligned(20, 4).unwrap();
+        assert_eq!(int, Interval::new(4, 20));
+        assert_eq!(coll.padding_created(), 2);
+        assert!(coll.take_exact_aligned(3, 4).is_none());
+
         let mut it = coll.iter();
-        assert_eq!(it.next().unwrap().len(), 20);
+        assert_eq!(*it.next().unwrap(), Interval::new(29, 2));
+        assert_eq!(*it.next().unwrap(), Interval::new(1, 3));
         assert!(it.next().is_none());
     }
 
     #[test]
-    fn take_enough_none() {
-        let mut coll = test_data();
-        coll.insert(Interval::new(15, 5));
-        assert!(coll.take_enough(15).is_none());
+    fn back_placement_end_aligned() {
+        let mut coll = IntervalsCollection::default();
+        coll.set_placement(SplitPlacement::Back);
+        coll.insert(Interval::new(1, 30));
+        let int = coll.take_exact_end_aligned(5, 8).unwrap();
+        assert_eq!(int, Interval::new(19, 5));
+        assert_eq!(coll.padding_created(), 7);
+        let int = coll.take_exact_end_aligned(15, 8).unwrap();
+        assert_eq!(int, Interval::new(1, 15));
+        assert!(coll.take_exact_end_aligned(1, 8).is_none());
+    }
+
+    fn random_starts(seed: u64, length: u64, align: u64, runs: usize) -> Vec<u64> {
+        let mut coll = IntervalsCollection::default();
+        coll.set_strategy(Strategy::Random);
+        coll.set_seed(seed);
+        coll.insert(Interval::new(0, 100));
+        (0..runs)
+            .map(|_| {
+                let int = coll.take_exact_aligned(length, align).unwrap();
+                assert_eq!(int.len(), length);
+                assert_eq!(coll.iter().map(|i| i.len()).sum::<u64>(), 100 - length);
+                coll.insert(int);
+                int.start()
+            })
+            .collect()
     }
 
     #[test]
-    fn take_enough_twice() {
-        let mut coll = test_data();
-        coll.insert(Interval::new(15, 5));
-        coll.insert(Interval::new(25, 3));
-        assert_eq!(coll.take_enough(3).unwrap().len(), 3);
-        assert_eq!(coll.take_enough(5).unwrap().len(), 5);
-        assert_eq!(coll.take_enough(10).unwrap().len(), 10);
-        assert!(coll.take_enough(1).is_none());
+    fn random_is_deterministic() {
+        assert_eq!(random_starts(7, 10, 1, 100), random_starts(7, 10, 1, 100));
+        assert_ne!(random_starts(7, 10, 1, 100), random_starts(8, 10, 1, 100));
     }
 
     #[test]
-    fn take_exact() {
-        let mut coll = test_data();
-        coll.insert(Interval::new(15, 5));
-        coll.insert(Interval::new(25, 3));
-        assert_eq!(coll.take_exact(2).unwrap().len(), 2);
-        assert_eq!(coll.take_exact(2).unwrap().len(), 2);
-        let mut it = coll.iter();
-        assert_eq!(it.next().unwrap().len(), 1);
-        assert_eq!(it.next().unwrap().len(), 3);
-        assert_eq!(it.next().unwrap().len(), 10);
-        assert!(it.next().is_none());
+    fn random_is_uniform() {
+        for &(length, align) in &[(10, 1), (10, 4)] {
+            let positions = (100 - length) / align + 1;
+            let runs = 1000 * positions as usize;
+            let mut hits = vec![0u64; positions as usize];
+            for start in random_starts(42, length, align, runs) {
+                assert_eq!(start % align, 0);
+                hits[(start / align) as usize] += 1;
+            }
+            // Chi-square with up to 90 degrees of freedom stays far below 200.
+            let chi2: f64 = hits
+                .iter()
+                .map(|&h| (h as f64 - 1000.0).powi(2) / 1000.0)
+                .sum();
+            assert!(chi2 < 200.0, "chi2 = {}", chi2);
+        }
     }
 
     #[test]
-    fn take_exact_align() {
+    fn random_chooses_blocks_uniformly() {
         let mut coll = IntervalsCollection::default();
-        let free_interval = Interval::new(0, 30);
-        coll.insert(free_interval);
+        coll.set_strategy(Strategy::Random);
+        for i in 0..4 {
+            coll.insert(Interval::new(i * 10, 5));
+        }
+        coll.insert(Interval::new(100, 2));
+        let mut hits = [0; 4];
+        for _ in 0..4000 {
+            let int = coll.take_enough(5).unwrap();
+            hits[(int.start() / 10) as usize] += 1;
+            coll.insert(int);
+        }
+        assert!(hits.iter().all(|&h| h > 850 && h < 1150), "{:?}", hits);
+    }
 
-        let len = 15;
-        let align = 2;
-        let int = coll.take_exact_aligned(len, align).unwrap();
-        assert_eq!(int.start() % align, 0);
-        assert_eq!(int.len(), len);
+    #[test]
+    fn state_hash() {
+        let empty = IntervalsCollection::default();
+        assert_eq!(empty.state_hash(), 0xcbf2_9ce4_8422_2325);
 
-        let len = 4;
-        let align = 4;
-        let int = coll.take_exact_aligned(len, align).unwrap();
-        assert_eq!(int.start() % align, 0);
-        assert_eq!(int.len(), len);
+        let mut a = IntervalsCollection::default();
+        a.insert(Interval::new(0, 10));
+        a.insert(Interval::new(20, 10));
+        let mut b = IntervalsCollection::default();
+        b.insert(Interval::new(25, 5));
+        b.insert(Interval::new(0, 10));
+        b.insert(Interval::new(20, 5));
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_eq!(a.state_hash(), 0x0d2b_ebd4_2b52_5b31);
 
-        let mut iter = coll.iter();
-        assert_eq!(*iter.next().unwrap(), Interval::new(15, 1));
-        assert_eq!(*iter.next().unwrap(), Interval::new(20, 10));
-        assert!(iter.next().is_none());
+        b.take_exact(1).unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
     }
 
     #[test]
-    fn take_exact_none() {
-        let mut coll = test_data();
-        coll.insert(Interval::new(15, 5));
-        coll.insert(Interval::new(25, 3));
-        assert!(coll.take_exact(20).is_none());
+    fn transfer_to() {
+        let mut from = test_data();
+        from.insert(Interval::new(15, 5));
+        from.insert(Interval::new(25, 3));
+        let mut to = IntervalsCollection::default();
+        to.insert(Interval::new(100, 10));
+
+        assert_eq!(from.transfer_to(&mut to, 12), 12);
+        let left: Vec<_> = from.iter().copied().collect();
+        assert_eq!(left, vec![Interval::new(17, 3), Interval::new(25, 3)]);
+        let moved: Vec<_> = to.iter().copied().collect();
+        let expected = vec![
+            Interval::new(15, 2),
+            Interval::new(0, 10),
+            Interval::new(100, 10),
+        ];
+        assert_eq!(moved, expected);
+
+        assert_eq!(from.transfer_to(&mut to, 100), 6);
+        assert!(from.iter().next().is_none());
+        assert_eq!(to.iter().map(|i| i.len()).sum::<u64>(), 28);
+        assert_eq!(from.transfer_to(&mut to, 1), 0);
+    }
+
+    #[test]
+    fn transfer_keeps_cursor() {
+        let mut from = IntervalsCollection::default();
+        from.set_strategy(Strategy::NextFit);
+        from.insert(Interval::new(0, 30));
+        from.insert(Interval::new(40, 60));
+        assert_eq!(from.take_exact(5), Some(Interval::new(0, 5)));
+
+        let mut to = IntervalsCollection::default();
+        assert_eq!(from.transfer_to(&mut to, 20), 20);
+        assert_eq!(to.iter().next(), Some(&Interval::new(40, 20)));
+        assert_eq!(from.take_exact(5), Some(Interval::new(5, 5)));
+    }
+
+    #[test]
+    fn simulate_insert() {
+        let mut state = 0x1234_5678_9abc_def0;
+        let mut coll = IntervalsCollection::default();
+        coll.insert(Interval::new(0, 1000));
+        let mut taken = Vec::new();
+        for _ in 0..2000 {
+            let rnd = xorshift(&mut state);
+            if rnd % 2 == 0 && !taken.is_empty() {
+                let int: Interval = taken.swap_remove((rnd >> 8) as usize % taken.len());
+                let predicted = coll.simulate_insert(int);
+                coll.insert(int);
+                assert!(coll.iter().any(|i| *i == predicted));
+            } else if let Some(int) = coll.take_exact(1 + (rnd >> 8) % 50) {
+                taken.push(int);
+            }
+        }
     }
 }
@@ -0,0 +1,220 @@
+use crate::interval::{ops, Interval};
+use std::iter;
+use std::sync::Arc;
+
+/// Part of full range, returned by `FrozenSubranges::segments`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Segment {
+    Free(Interval),
+    Used(Interval),
+}
+
+impl Segment {
+    pub fn interval(&self) -> Interval {
+        match self {
+            Segment::Free(int) | Segment::Used(int) => *int,
+        }
+    }
+
+    pub fn is_free(&self) -> bool {
+        matches!(self, Segment::Free(_))
+    }
+}
+
+/// Immutable snapshot of `Subranges` state.
+///
+/// Cheap to clone and safe to share between threads. Later changes of the
+/// allocator, the snapshot was taken from, are not visible through it.
+#[derive(Debug, Clone)]
+pub struct FrozenSubranges {
+    state: Arc<FrozenState>,
+}
+
+#[derive(Debug)]
+struct FrozenState {
+    free: Vec<Interval>,
+    free_len: u64,
+    largest_free_len: u64,
+    range: Interval,
+}
+
+impl FrozenSubranges {
+    /// `free` must be ordered by start.
+    pub(crate) fn new<'a>(free: impl Iterator<Item = &'a Interval>, range: Interval) -> Self {
+        let free: Vec<Interval> = free.copied().collect();
+        let free_len = free.iter().map(|i| i.len()).sum();
+        let largest_free_len = free.iter().map(|i| i.len()).max().unwrap_or(0);
+        let state = FrozenState {
+            free,
+            free_len,
+            largest_free_len,
+            range,
+        };
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    /// Free subranges ordered by start.
+    pub fn free_subranges(&self) -> impl Iterator<Item = &Interval> {
+        self.state.free.iter()
+    }
+
+    /// Free and used parts of full range, ordered by start.
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        let free_iter = self.state.free.iter().copied();
+        let mut used = ops::gaps(free_iter.clone(), self.state.range).peekable();
+        let mut free = free_iter.peekable();
+        iter::from_fn(move || match (free.peek(), used.peek()) {
+            (Some(f), Some(u)) if u.start() < f.start() => used.next().map(Segment::Used),
+            (Some(_), _) => free.next().map(Segment::Free),
+            (None, _) => used.next().map(Segment::Used),
+        })
+    }
+
+    /// Map of full range, `width` characters long, but not longer than `len()`.
+    /// Every character stands for an equal part of range: `.` if it is free, `#` if
+    /// it is used, `+` if it is partially used.
+    pub fn render_map(&self, width: usize) -> String {
+        let range = self.state.range;
+        let width = (width as u64).min(range.len());
+        let mut map = String::with_capacity(width as usize);
+        let mut free = self.state.free.as_slice();
+        let bound = |i: u64| {
+            let offset = u128::from(range.len()) * u128::from(i) / u128::from(width);
+            range.start() + offset as u64
+        };
+        for i in 0..width {
+            let cell = Interval::from_bounds(bound(i), bound(i + 1));
+            while free.first().is_some_and(|f| f.end() <= cell.start()) {
+                free = &free[1..];
+            }
+            let inside = free.iter().take_while(|f| f.start() < cell.end());
+            let free_len: u64 = inside
+                .filter_map(|f| f.intersection(&cell))
+                .map(|f| f.len())
+                .sum();
+            map.push(match free_len {
+                0 => '#',
+                len if len == cell.len() => '.',
+                _ => '+',
+            });
+        }
+        map
+    }
+
+    /// Total length of free subranges.
+    pub fn free_len(&self) -> u64 {
+        self.state.free_len
+    }
+
+    /// Total length of taken subranges.
+    pub fn used_len(&self) -> u64 {
+        self.state.range.len() - self.state.free_len
+    }
+
+    /// Number of free subranges.
+    pub fn free_count(&self) -> usize {
+        self.state.free.len()
+    }
+
+    /// Length of the longest free subrange, or 0.
+    pub fn largest_free_len(&self) -> u64 {
+        self.state.largest_free_len
+    }
+
+    /// Same as `Subranges::fragmentation` at the moment of snapshot.
+    pub fn fragmentation(&self) -> f64 {
+        match self.state.free_len {
+            0 => 0.0,
+            free_len => 1.0 - self.state.largest_free_len as f64 / free_len as f64,
+        }
+    }
+
+    /// Full range.
+    pub fn range(&self) -> Interval {
+        self.state.range
+    }
+
+    /// Length of full range.
+    pub fn len(&self) -> u64 {
+        self.state.range.len()
+    }
+
+    /// True if `self.len()` is zero.
+    pub fn is_empty(&self) -> bool {
+        self.state.range.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frozen::{FrozenSubranges, Segment};
+    use crate::interval::Interval;
+    use crate::Subranges;
+
+    #[test]
+    fn snapshot_is_detached() {
+        let mut subranges = Subranges::new(Interval::new(0, 100));
+        subranges.take_free_subrange(30).unwrap();
+        let frozen = subranges.freeze();
+        subranges.take_free_subrange(30).unwrap();
+
+        assert_eq!(frozen.len(), 100);
+        assert_eq!(frozen.free_len(), 70);
+        assert_eq!(frozen.used_len(), 30);
+        let free: Vec<_> = frozen.free_subranges().copied().collect();
+        assert_eq!(free, vec![Interval::new(30, 70)]);
+    }
+
+    #[test]
+    fn segments_and_map() {
+        let mut subranges = Subranges::new(Interval::new(100, 40));
+        let a = subranges.take_free_subrange(10).unwrap();
+        subranges.take_free_subrange(15).unwrap();
+        subranges.erase_subrange(a);
+        let frozen = subranges.freeze();
+
+        let segments: Vec<_> = frozen.segments().collect();
+        assert_eq!(
+            segments,
+            [
+                Segment::Free(Interval::new(100, 10)),
+                Segment::Used(Interval::new(110, 15)),
+                Segment::Free(Interval::new(125, 15)),
+            ]
+        );
+        assert!(segments[0].is_free());
+        let total: u64 = segments.iter().map(|s| s.interval().len()).sum();
+        assert_eq!(total, frozen.len());
+
+        assert_eq!(frozen.render_map(8), "..###...");
+        assert_eq!(frozen.render_map(6), ".+#+..");
+        assert_eq!(frozen.render_map(4), ".#+.");
+        assert_eq!(frozen.render_map(100).len(), 40);
+        assert_eq!((frozen.free_count(), frozen.largest_free_len()), (2, 15));
+        assert_eq!(frozen.fragmentation(), 1.0 - 15.0 / 25.0);
+        assert_eq!(frozen.range(), Interval::new(100, 40));
+
+        subranges.take_free_subrange(10).unwrap();
+        subranges.take_free_subrange(15).unwrap();
+        let full = subranges.freeze();
+        let segments: Vec<_> = full.segments().collect();
+        assert_eq!(segments, [Segment::Used(Interval::new(100, 40))]);
+        assert_eq!(full.render_map(3), "###");
+        assert_eq!(full.fragmentation(), 0.0);
+    }
+
+    #[test]
+    fn shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FrozenSubranges>();
+
+        let mut subranges = Subranges::new(Interval::new(0, 10));
+        subranges.take_free_subrange(2).unwrap();
+        let frozen = subranges.freeze();
+        let clone = frozen.clone();
+        let used = std::thread::spawn(move || clone.used_len()).join().unwrap();
+        assert_eq!(used, frozen.used_len());
+    }
+}
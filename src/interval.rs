@@ -1,70 +1,443 @@
+pub mod ops;
+
 use std::cmp;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Bound, Index, IndexMut, Range, RangeBounds, RangeInclusive};
+
+/// Least multiple of `align`, which is not less than `value`.
+/// Return `None` if it doesn't fit into `u64`. `align` 0 is treated as 1.
+pub fn align_up(value: u64, align: u64) -> Option<u64> {
+    if align.is_power_of_two() {
+        let mask = align - 1;
+        return value.checked_add(mask).map(|v| v & !mask);
+    }
+    match value % cmp::max(align, 1) {
+        0 => Some(value),
+        rem => value.checked_add(align - rem),
+    }
+}
+
+/// Greatest multiple of `align`, which is not greater than `value`.
+/// `align` 0 is treated as 1.
+pub fn align_down(value: u64, align: u64) -> u64 {
+    if align.is_power_of_two() {
+        return value & !(align - 1);
+    }
+    value - value % cmp::max(align, 1)
+}
+
+/// Minimal sorted list of disjoint intervals, covering the same integers as
+/// `intervals`: overlapping and adjacent intervals are joined, empty ones are dropped.
+/// Output is suitable as input for `ops` functions. Takes `O(n log n)` time.
+pub fn normalize(intervals: impl IntoIterator<Item = Interval>) -> Vec<Interval> {
+    let mut sorted: Vec<_> = intervals.into_iter().filter(|i| !i.is_empty()).collect();
+    sorted.sort_unstable();
+    let mut normalized: Vec<Interval> = Vec::with_capacity(sorted.len());
+    for int in sorted {
+        match normalized.last_mut() {
+            Some(last) if last.near(&int) => *last = last.connect(&int),
+            _ => normalized.push(int),
+        }
+    }
+    normalized
+}
 
 /// Represent integer interval.
+///
+/// Intervals can be built in const context, e.g. for static region tables:
+///
+/// ```
+/// use subranges::interval::Interval;
+/// use subranges::Subranges;
+///
+/// static REGIONS: [Interval; 2] = [Interval::new(0, 4096), Interval::from_bounds(8192, 16384)];
+/// const TOTAL: u64 = REGIONS[0].len() + REGIONS[1].len();
+///
+/// let mut heaps: Vec<_> = REGIONS.iter().map(|r| Subranges::new(*r)).collect();
+/// assert_eq!(TOTAL, 12288);
+/// assert_eq!(heaps[1].take_free_subrange(16), Some(Interval::new(8192, 16)));
+/// ```
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct Interval {
     start: u64,
-    length: u64,
+    end: u64,
 }
 
 impl Interval {
     /// Create new interval [`start`; start + length).
-    pub fn new(start: u64, length: u64) -> Self {
-        Self { start, length }
+    /// # Panics
+    /// * Panics if `start + length` doesn't fit into `u64`, in debug and release builds
+    pub const fn new(start: u64, length: u64) -> Self {
+        match Self::checked_new(start, length) {
+            Some(interval) => interval,
+            None => panic!("Interval end must fit into u64"),
+        }
+    }
+
+    /// Create new interval [`start`; start + length).
+    /// Return `None` if `start + length` doesn't fit into `u64`.
+    pub const fn checked_new(start: u64, length: u64) -> Option<Self> {
+        match start.checked_add(length) {
+            Some(end) => Some(Self { start, end }),
+            None => None,
+        }
+    }
+
+    /// Create new interval [`start`; `end`). `start == end` gives empty interval.
+    /// # Panics
+    /// * Panics if `end` < `start`
+    pub const fn from_bounds(start: u64, end: u64) -> Self {
+        match Self::try_from_bounds(start, end) {
+            Ok(interval) => interval,
+            Err(_) => panic!("Interval end must be >= start"),
+        }
+    }
+
+    /// Create new interval [`start`; `end`).
+    /// Return error if `end` < `start`.
+    pub const fn try_from_bounds(start: u64, end: u64) -> Result<Self, IntervalError> {
+        match start <= end {
+            true => Ok(Self { start, end }),
+            false => Err(IntervalError::InvalidEnd),
+        }
+    }
+
+    /// Create new interval [`start`; start + length).
+    /// Return error if `start + length` doesn't fit into `u64`.
+    pub fn try_new(start: u64, length: u64) -> Result<Self, IntervalError> {
+        Self::checked_new(start, length).ok_or(IntervalError::Overflow)
+    }
+
+    /// Move `self` to `start`, keeping its length.
+    pub fn set_start(&mut self, start: u64) -> Result<(), IntervalError> {
+        *self = Self::try_new(start, self.len())?;
+        Ok(())
+    }
+
+    /// Move `self` by `delta`, keeping its length.
+    /// Return `None` if some end of result doesn't fit into `u64`.
+    pub fn shift(&self, delta: i64) -> Option<Self> {
+        let start = self.start.checked_add_signed(delta)?;
+        Self::checked_new(start, self.len())
+    }
+
+    /// Move `self` forward by `delta`. Return `None` on overflow.
+    pub fn shift_up(&self, delta: u64) -> Option<Self> {
+        Self::checked_new(self.start.checked_add(delta)?, self.len())
+    }
+
+    /// Move `self` back by `delta`. Return `None` on underflow.
+    pub fn shift_down(&self, delta: u64) -> Option<Self> {
+        Self::checked_new(self.start.checked_sub(delta)?, self.len())
+    }
+
+    /// Move `self` by `delta`, keeping its length. Stop at `0` or at `u64::MAX`.
+    pub fn saturating_shift(&self, delta: i64) -> Self {
+        let len = self.len();
+        let start = cmp::min(self.start.saturating_add_signed(delta), u64::MAX - len);
+        Interval {
+            start,
+            end: start + len,
+        }
+    }
+
+    /// Change length of `self`, keeping its start.
+    pub fn set_len(&mut self, length: u64) -> Result<(), IntervalError> {
+        *self = Self::try_new(self.start, length)?;
+        Ok(())
+    }
+
+    /// Move end of `self` forward to `end`.
+    /// Return error if `end` < `self.end()`.
+    pub fn grow_to(&mut self, end: u64) -> Result<(), IntervalError> {
+        if end < self.end {
+            return Err(IntervalError::InvalidEnd);
+        }
+        self.end = end;
+        Ok(())
+    }
+
+    /// Move end of `self` back to `end`.
+    /// Return error if `end` > `self.end()` or `end` < `self.start()`.
+    pub fn shrink_to(&mut self, end: u64) -> Result<(), IntervalError> {
+        if end > self.end || end < self.start {
+            return Err(IntervalError::InvalidEnd);
+        }
+        self.end = end;
+        Ok(())
     }
 
     /// Length of interval. Count of integers in `self`.
-    pub fn len(&self) -> u64 {
-        self.length
+    pub const fn len(&self) -> u64 {
+        self.end - self.start
     }
 
     /// True if self.len() == 0.
-    pub fn is_empty(&self) -> bool {
-        self.length == 0
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
     }
 
     /// First intager in `self`.
-    pub fn start(&self) -> u64 {
+    pub const fn start(&self) -> u64 {
         self.start
     }
 
     /// Integer after last integer in `self`.
-    pub fn end(&self) -> u64 {
-        self.start + self.length
+    pub const fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Last integer in `self`, or `None` if `self` is empty.
+    pub fn last(&self) -> Option<u64> {
+        match self.is_empty() {
+            true => None,
+            false => Some(self.end - 1),
+        }
+    }
+
+    /// Same as `last`.
+    pub fn end_inclusive(&self) -> Option<u64> {
+        self.last()
     }
 
     /// Return `true` if `self` contains `p`. Else `false`.
-    pub fn contains(&self, p: u64) -> bool {
-        p >= self.start && p < self.end()
+    /// An empty interval contains no points.
+    pub const fn contains(&self, p: u64) -> bool {
+        p >= self.start && p < self.end
+    }
+
+    /// Return `true` if every integer of `other` is in `self`.
+    /// An empty `other` has no integers, so it is contained in any interval.
+    pub fn contains_interval(&self, other: &Self) -> bool {
+        use IntervalRelation::*;
+        other.is_empty()
+            || matches!(
+                self.relation(other),
+                Contains | StartedBy | FinishedBy | Equal
+            )
     }
 
     /// Test if `other` has got common integers with `self`.
+    /// Always `false`, if any of the intervals is empty.
     pub fn intersect(&self, other: &Self) -> bool {
-        let connected = self.connect(other);
-        connected.length < (self.length + other.length)
+        use IntervalRelation::*;
+        let relation = self.relation(other);
+        !self.is_empty() && !other.is_empty() && !matches!(relation, Before | Meets | MetBy | After)
+    }
+
+    /// Relation of `self` to `other` in Allen's interval algebra.
+    ///
+    /// Relation is defined by comparing bounds, so it is defined for empty intervals
+    /// too. `Equal` is checked first, then `Before`, `After`, `Meets` and `MetBy`. So an
+    /// empty interval at a bound of another one meets it or is met by it, and an empty
+    /// interval strictly inside of another one is `ContainedBy` it.
+    pub fn relation(&self, other: &Self) -> IntervalRelation {
+        use IntervalRelation::*;
+        let (s1, e1, s2, e2) = (self.start, self.end, other.start, other.end);
+        if s1 == s2 && e1 == e2 {
+            return Equal;
+        }
+        if e1 < s2 {
+            return Before;
+        }
+        if e2 < s1 {
+            return After;
+        }
+        if e1 == s2 {
+            return Meets;
+        }
+        if e2 == s1 {
+            return MetBy;
+        }
+        match (s1.cmp(&s2), e1.cmp(&e2)) {
+            (Ordering::Equal, Ordering::Less) => Starts,
+            (Ordering::Equal, _) => StartedBy,
+            (Ordering::Greater, Ordering::Equal) => Finishes,
+            (_, Ordering::Equal) => FinishedBy,
+            (Ordering::Less, Ordering::Less) => Overlaps,
+            (Ordering::Less, _) => Contains,
+            (Ordering::Greater, Ordering::Greater) => OverlappedBy,
+            (Ordering::Greater, _) => ContainedBy,
+        }
+    }
+
+    /// Return interval of integers, contained both in `self` and in `other`.
+    /// Return `None` if there are no such integers.
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let start = cmp::max(self.start, other.start);
+        let end = cmp::min(self.end, other.end);
+        match start < end {
+            true => Some(Interval { start, end }),
+            false => None,
+        }
+    }
+
+    /// Remove integers of `other` from `self`. Return parts of `self` before
+    /// and after `other`, if they aren't empty. An empty `other` removes nothing,
+    /// but still splits `self` at its start.
+    pub fn subtract(&self, other: &Self) -> (Option<Self>, Option<Self>) {
+        let before = Interval {
+            start: self.start,
+            end: cmp::min(self.end, other.start),
+        };
+        let after = Interval {
+            start: cmp::max(self.start, other.end),
+            end: self.end,
+        };
+        let non_empty = |i: Interval| match i.start < i.end {
+            true => Some(i),
+            false => None,
+        };
+        (non_empty(before), non_empty(after))
     }
 
     /// Return `true` if no integers between `self` and `other` exists.
+    /// An empty interval is near to another one, if it lies inside of it
+    /// or at one of its bounds.
     pub fn near(&self, other: &Self) -> bool {
-        let connected = self.connect(other);
-        connected.length <= (self.length + other.length)
+        !matches!(
+            self.relation(other),
+            IntervalRelation::Before | IntervalRelation::After
+        )
+    }
+
+    /// Return `true` if there are at most `max_gap` integers between `self` and `other`.
+    /// `near_within(other, 0)` is the same as `near(other)`.
+    pub fn near_within(&self, other: &Self, max_gap: u64) -> bool {
+        self.gap_to(other).map_or(true, |gap| gap <= max_gap)
+    }
+
+    /// If `near_within(other, max_gap)`, return union of intervals and the gap between
+    /// them. Integers of the gap are swallowed by the result. Else `None`.
+    pub fn try_join_within(&self, other: &Self, max_gap: u64) -> Option<Self> {
+        match self.near_within(other, max_gap) {
+            true => Some(self.connect(other)),
+            false => None,
+        }
+    }
+
+    /// Count of integers between `self` and `other`: `Some(0)` if they are adjacent,
+    /// `None` if they intersect.
+    pub fn gap_to(&self, other: &Self) -> Option<u64> {
+        let start = cmp::max(self.start, other.start);
+        let end = cmp::min(self.end, other.end);
+        start.checked_sub(end)
     }
 
     /// Return interval from min `start` to max `end`.
     pub fn connect(&self, other: &Self) -> Self {
         let min_start = cmp::min(self.start, other.start);
-        let max_end = cmp::max(self.end(), other.end());
-        Interval::new(min_start, max_end - min_start)
+        let max_end = cmp::max(self.end, other.end);
+        Interval {
+            start: min_start,
+            end: max_end,
+        }
+    }
+
+    /// Smallest interval, which contains `self` and `p`. An empty `self` contains
+    /// nothing, so only `p` is covered then.
+    /// # Panics
+    /// * Panics if `p` is `u64::MAX`: interval can't contain it
+    pub fn extend_to_contain(&self, p: u64) -> Self {
+        self.checked_extend_to_contain(p)
+            .expect("Interval can't contain u64::MAX")
+    }
+
+    /// Same as `extend_to_contain`, but return `None` if `p` is `u64::MAX`.
+    pub fn checked_extend_to_contain(&self, p: u64) -> Option<Self> {
+        let point = Interval::checked_new(p, 1)?;
+        Some(self.extend_to_cover(&point))
+    }
+
+    /// Smallest interval, which contains all integers of `self` and `other`.
+    /// Unlike `connect`, empty intervals are ignored, wherever they start.
+    pub fn extend_to_cover(&self, other: &Self) -> Self {
+        match (self.is_empty(), other.is_empty()) {
+            (_, true) => *self,
+            (true, false) => *other,
+            (false, false) => self.connect(other),
+        }
+    }
+
+    /// Return `[self.start - before; self.end + after)`.
+    /// Return `None` if start underflows or end overflows.
+    pub fn grow(&self, before: u64, after: u64) -> Option<Self> {
+        let start = self.start.checked_sub(before)?;
+        let end = self.end.checked_add(after)?;
+        Some(Self { start, end })
+    }
+
+    /// Same as `grow`, but padding is clamped to `bounds`, so it never fails.
+    /// `self` itself is never cut. Side of `self`, which is out of `bounds`, isn't padded.
+    pub fn grow_clamped(&self, before: u64, after: u64, bounds: &Interval) -> Self {
+        let reaches = |p: u64| bounds.start <= p && p <= bounds.end;
+        let min_start = if reaches(self.start) {
+            bounds.start
+        } else {
+            self.start
+        };
+        let max_end = if reaches(self.end) {
+            bounds.end
+        } else {
+            self.end
+        };
+        Self {
+            start: cmp::max(self.start.saturating_sub(before), min_start),
+            end: cmp::min(self.end.saturating_add(after), max_end),
+        }
+    }
+
+    /// Return `[self.start + before; self.end - after)`.
+    /// Return `None` if `before + after` > `self.len()`.
+    pub fn shrink(&self, before: u64, after: u64) -> Option<Self> {
+        let rest = self.len().checked_sub(before)?.checked_sub(after)?;
+        Some(Self {
+            start: self.start + before,
+            end: self.start + before + rest,
+        })
     }
 
     /// Split `self` into two intervals.
     /// First - `[self.start; self.start + length)`, second - `[self.start + length; self.end)`.
     /// # Panics
-    /// * Panics if `length` > `self.length`
+    /// * Panics if `length` > `self.len()`
     pub fn split(&self, length: u64) -> (Self, Self) {
-        let left = Self::new(self.start, length);
-        let right = Self::new(self.start + length, self.length - length);
-        (left, right)
+        self.try_split(length)
+            .expect("Split length must be <= interval length")
+    }
+
+    /// Same as `split`, but return `None` if `length` > `self.len()`.
+    pub fn try_split(&self, length: u64) -> Option<(Self, Self)> {
+        if length > self.len() {
+            return None;
+        }
+        let middle = self.start + length;
+        let left = Interval {
+            start: self.start,
+            end: middle,
+        };
+        let right = Interval {
+            start: middle,
+            end: self.end,
+        };
+        Some((left, right))
+    }
+
+    /// Split `self` into `[self.start; position)` and `[position; self.end)`.
+    /// Return error if `position` is out of `[self.start; self.end]`.
+    pub fn split_at(&self, position: u64) -> Result<(Self, Self), SplitError> {
+        if position < self.start {
+            return Err(SplitError::BeforeStart);
+        }
+        if position > self.end {
+            return Err(SplitError::AfterEnd);
+        }
+        Ok(self.split(position - self.start))
     }
 
     /// If intervals is near, return their union. Else `None`.
@@ -75,36 +448,520 @@ impl Interval {
         }
     }
 
+    /// Return point of `target` with the same offset, as `p` has in `self`.
+    /// Return `None` if `p` isn't in `self` or offset doesn't fit into `target`.
+    pub fn map_point(&self, p: u64, target: &Interval) -> Option<u64> {
+        if !self.contains(p) {
+            return None;
+        }
+        let offset = p - self.start;
+        match offset < target.len() {
+            true => Some(target.start + offset),
+            false => None,
+        }
+    }
+
+    /// Return interval of `target` with the same offset and length, as `sub` has in `self`.
+    /// Return `None` if `sub` isn't inside `self` or doesn't fit into `target`.
+    pub fn map_interval(&self, sub: &Interval, target: &Interval) -> Option<Interval> {
+        if sub.start < self.start || sub.end > self.end {
+            return None;
+        }
+        let offset = sub.start - self.start;
+        match offset + sub.len() <= target.len() {
+            true => Some(Interval::new(target.start + offset, sub.len())),
+            false => None,
+        }
+    }
+
+    /// Every start of `length` integers inside `self`, which is multiple of `align`,
+    /// in ascending order. `align` 0 is treated as 1.
+    pub fn placements(&self, length: u64, align: u64) -> impl Iterator<Item = u64> {
+        let align = cmp::max(align, 1);
+        let first = match align {
+            1 => Some(self.start),
+            _ => align_up(self.start, align),
+        };
+        let last = self.end.checked_sub(length);
+        let steps = match (first, last) {
+            (Some(first), Some(last)) if first <= last => Some((first, (last - first) / align)),
+            _ => None,
+        };
+        steps
+            .into_iter()
+            .flat_map(move |(first, steps)| (0..=steps).map(move |k| first + k * align))
+    }
+
+    /// `self` with offsets relative to `base.start()`.
+    /// Return `None` if `self` isn't inside `base`.
+    pub fn relative_to(&self, base: &Interval) -> Option<Interval> {
+        if self.start < base.start || self.end > base.end {
+            return None;
+        }
+        Some(Interval::new(self.start - base.start, self.len()))
+    }
+
+    /// Inverse of `relative_to`: `self`, relative to `base.start()`, in coordinates of `base`.
+    /// Return `None` if the result doesn't fit into `base`.
+    pub fn rebase_onto(&self, base: &Interval) -> Option<Interval> {
+        let rebased = self.shift_up(base.start)?;
+        match rebased.end <= base.end {
+            true => Some(rebased),
+            false => None,
+        }
+    }
+
+    /// Elements of `slice` with indices in `self`.
+    /// Return `None` if `self` doesn't fit into `slice` or into `usize`.
+    pub fn index<'a, T>(&self, slice: &'a [T]) -> Option<&'a [T]> {
+        slice.get(self.usize_range()?)
+    }
+
+    /// Same as `index`, but mutable.
+    pub fn index_mut<'a, T>(&self, slice: &'a mut [T]) -> Option<&'a mut [T]> {
+        slice.get_mut(self.usize_range()?)
+    }
+
+    fn usize_range(&self) -> Option<Range<usize>> {
+        let start = usize::try_from(self.start).ok()?;
+        let end = usize::try_from(self.end).ok()?;
+        Some(start..end)
+    }
+
+    /// Multiply start and length by `factor`, e.g. to convert blocks to bytes.
+    /// Return `None` on overflow.
+    pub fn scale(&self, factor: u64) -> Option<Interval> {
+        let start = self.start.checked_mul(factor)?;
+        let length = self.len().checked_mul(factor)?;
+        Interval::checked_new(start, length)
+    }
+
+    /// Divide start and length by `factor`, e.g. to convert bytes to blocks.
+    /// Return `None` if `factor` is 0 or start or length isn't multiple of `factor`.
+    pub fn scale_down(&self, factor: u64) -> Option<Interval> {
+        if factor == 0 || self.start % factor != 0 || self.len() % factor != 0 {
+            return None;
+        }
+        Some(Interval::new(self.start / factor, self.len() / factor))
+    }
+
+    /// Divide bounds by `factor`, rounding outward: start is rounded down and end
+    /// is rounded up. So the result covers every unit, which `self` touches.
+    /// Empty `self` touches none: the result is empty at `start / factor`.
+    /// Return `None` if `factor` is 0.
+    pub fn scale_down_lossy(&self, factor: u64) -> Option<Interval> {
+        if factor == 0 {
+            return None;
+        }
+        if self.is_empty() {
+            return Some(Interval::new(self.start / factor, 0));
+        }
+        Some(Interval::from_bounds(
+            self.start / factor,
+            self.end.div_ceil(factor),
+        ))
+    }
+
+    /// Same integers as std range `start..end`.
+    pub fn as_range(&self) -> Range<u64> {
+        self.start..self.end
+    }
+
+    /// Same integers as std range `start..=end - 1`, or `None` if `self` is empty.
+    pub fn as_inclusive_range(&self) -> Option<RangeInclusive<u64>> {
+        self.last().map(|last| self.start..=last)
+    }
+
+    /// Part of `self` from its first integer, which is multiple of `align`.
+    /// Return `None` if there is no such integer. `align` 0 is treated as 1.
+    pub fn align_start_up(&self, align: u64) -> Option<Self> {
+        let start = align_up(self.start, align)?;
+        match start < self.end {
+            true => Some(Interval {
+                start,
+                end: self.end,
+            }),
+            false => None,
+        }
+    }
+
+    /// Length of `self.align_start_up(align)`, or 0 if it is `None`.
+    pub fn aligned_len(&self, align: u64) -> u64 {
+        self.align_start_up(align).map_or(0, |i| i.len())
+    }
+
+    /// Split `self` into `n` consecutive intervals, whose lengths differ by at most 1.
+    /// Longer ones go first. If `n` > `self.len()`, the last ones are empty.
+    /// # Panics
+    /// * Panics if `n` is 0
+    pub fn split_n(&self, n: usize) -> impl Iterator<Item = Interval> {
+        assert!(n > 0, "Count of parts must be > 0");
+        let n = n as u64;
+        let (quotient, remainder) = (self.len() / n, self.len() % n);
+        let start = self.start;
+        (0..n).map(move |k| {
+            let offset = k * quotient + cmp::min(k, remainder);
+            let length = quotient + u64::from(k < remainder);
+            Interval::new(start + offset, length)
+        })
+    }
+
+    /// Split `self` into two halves. The first one is longer, if length is odd.
+    pub fn bisect(&self) -> (Self, Self) {
+        self.split(self.len() - self.len() / 2)
+    }
+
+    /// Consecutive sub-intervals of `chunk_len` integers. The last one is shorter,
+    /// if `self.len()` isn't multiple of `chunk_len`.
+    /// # Panics
+    /// * Panics if `chunk_len` is 0
+    pub fn chunks(&self, chunk_len: u64) -> Chunks {
+        assert!(chunk_len > 0, "Chunk length must be > 0");
+        Chunks {
+            rest: *self,
+            chunk_len,
+        }
+    }
+
+    /// Same as `chunks`, but the shorter last chunk is not yielded.
+    /// It is available through `ChunksExact::remainder`.
+    /// # Panics
+    /// * Panics if `chunk_len` is 0
+    pub fn chunks_exact(&self, chunk_len: u64) -> ChunksExact {
+        assert!(chunk_len > 0, "Chunk length must be > 0");
+        let exact_len = self.len() - self.len() % chunk_len;
+        let (exact, remainder) = self.split(exact_len);
+        ChunksExact {
+            chunks: exact.chunks(chunk_len),
+            remainder,
+        }
+    }
+
     /// Return iterator over integers in `self`.
-    pub fn iter(&self) -> IntervalIterator {
+    pub fn iter(&self) -> IntervalIterator<'_> {
         IntervalIterator {
-            interval: &self,
-            counter: 0,
+            front: self.start,
+            back: self.end,
+            interval: PhantomData,
+        }
+    }
+}
+
+/// Relation of interval `a` to interval `b`, returned by `a.relation(&b)`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum IntervalRelation {
+    /// `a` ends before `b` starts, with a gap between them.
+    Before,
+    /// `a` ends exactly where `b` starts.
+    Meets,
+    /// `a` starts before `b` and ends inside of it.
+    Overlaps,
+    /// `a` starts with `b` and ends before it.
+    Starts,
+    /// `a` lies strictly inside of `b`.
+    ContainedBy,
+    /// `a` starts after `b` and ends with it.
+    Finishes,
+    /// `a` and `b` have the same bounds.
+    Equal,
+    /// `a` starts before `b` and ends with it.
+    FinishedBy,
+    /// `b` lies strictly inside of `a`.
+    Contains,
+    /// `a` starts with `b` and ends after it.
+    StartedBy,
+    /// `a` starts inside of `b` and ends after it.
+    OverlappedBy,
+    /// `a` starts exactly where `b` ends.
+    MetBy,
+    /// `a` starts after `b` ends, with a gap between them.
+    After,
+}
+
+impl IntervalRelation {
+    /// Relation of `b` to `a`, if `self` is relation of `a` to `b`.
+    pub fn converse(self) -> Self {
+        use IntervalRelation::*;
+        match self {
+            Before => After,
+            Meets => MetBy,
+            Overlaps => OverlappedBy,
+            Starts => StartedBy,
+            ContainedBy => Contains,
+            Finishes => FinishedBy,
+            Equal => Equal,
+            FinishedBy => Finishes,
+            Contains => ContainedBy,
+            StartedBy => Starts,
+            OverlappedBy => Overlaps,
+            MetBy => Meets,
+            After => Before,
+        }
+    }
+}
+
+/// Error of fallible interval construction and mutation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IntervalError {
+    /// End of interval doesn't fit into `u64`.
+    Overflow,
+    /// Requested end is out of allowed bounds.
+    InvalidEnd,
+}
+
+impl fmt::Display for IntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntervalError::Overflow => f.write_str("interval end overflows u64"),
+            IntervalError::InvalidEnd => f.write_str("interval end is out of allowed bounds"),
         }
     }
 }
 
+impl Error for IntervalError {}
+
+/// Error of `Interval::split_at`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SplitError {
+    /// Position is less than start of interval.
+    BeforeStart,
+    /// Position is greater than end of interval.
+    AfterEnd,
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::BeforeStart => f.write_str("split position is before interval start"),
+            SplitError::AfterEnd => f.write_str("split position is after interval end"),
+        }
+    }
+}
+
+impl Error for SplitError {}
+
+impl Interval {
+    /// Writes `start..end`, formatting both bounds with `fmt_bound`, so every
+    /// radix shares one layout and honours the same formatter flags.
+    fn fmt_bounds(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        fmt_bound: fn(&u64, &mut fmt::Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        fmt_bound(&self.start, f)?;
+        f.write_str("..")?;
+        fmt_bound(&self.end, f)
+    }
+}
+
+/// Formats as `start..end`, e.g. `4096..8192`.
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_bounds(f, fmt::Display::fmt)
+    }
+}
+
+/// Formats as `start..end` in lower hex. `{:#x}` gives `0x1000..0x2000`.
+impl fmt::LowerHex for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_bounds(f, fmt::LowerHex::fmt)
+    }
+}
+
+/// Formats as `start..end` in upper hex. `{:#X}` gives `0x1000..0x2000`.
+impl fmt::UpperHex for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_bounds(f, fmt::UpperHex::fmt)
+    }
+}
+
+/// `Interval` is a half-open range, so it bounds `[start; end)`.
+///
+/// The end bound is always `Excluded(end)`. An interval ending at `u64::MAX`
+/// is representable, but `u64::MAX` itself can never be contained in one.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use subranges::interval::Interval;
+///
+/// let map: BTreeMap<u64, &str> = vec![(1, "a"), (4, "b"), (8, "c")].into_iter().collect();
+/// let found: Vec<_> = map.range(Interval::new(2, 7)).map(|(_, v)| *v).collect();
+/// assert_eq!(found, vec!["b", "c"]);
+/// ```
+impl RangeBounds<u64> for Interval {
+    fn start_bound(&self) -> Bound<&u64> {
+        Bound::Included(&self.start)
+    }
+
+    fn end_bound(&self) -> Bound<&u64> {
+        Bound::Excluded(&self.end)
+    }
+}
+
+/// Range with `end` < `start` is empty, so it gives empty interval at `start`.
+impl From<Range<u64>> for Interval {
+    fn from(range: Range<u64>) -> Self {
+        Interval {
+            start: range.start,
+            end: cmp::max(range.start, range.end),
+        }
+    }
+}
+
+/// Range with `end` < `start` is empty, so it gives empty interval at `start`.
+///
+/// # Panics
+/// * Panics if `end` is `u64::MAX`: interval can't contain `u64::MAX`.
+impl From<RangeInclusive<u64>> for Interval {
+    fn from(range: RangeInclusive<u64>) -> Self {
+        let (start, end) = range.into_inner();
+        let end = end.checked_add(1).expect("Interval can't contain u64::MAX");
+        Interval::from(start..end)
+    }
+}
+
+impl From<Range<u32>> for Interval {
+    fn from(range: Range<u32>) -> Self {
+        Interval::from(u64::from(range.start)..u64::from(range.end))
+    }
+}
+
+impl From<Range<usize>> for Interval {
+    fn from(range: Range<usize>) -> Self {
+        Interval::from(range.start as u64..range.end as u64)
+    }
+}
+
+impl From<Interval> for Range<u64> {
+    fn from(interval: Interval) -> Self {
+        interval.as_range()
+    }
+}
+
+/// Same as `Interval::index`, but panics if `interval` doesn't fit into the slice.
+impl<T> Index<Interval> for [T] {
+    type Output = [T];
+
+    fn index(&self, interval: Interval) -> &[T] {
+        interval.index(self).expect("Interval out of slice bounds")
+    }
+}
+
+/// Same as `Interval::index_mut`, but panics if `interval` doesn't fit into the slice.
+impl<T> IndexMut<Interval> for [T] {
+    fn index_mut(&mut self, interval: Interval) -> &mut [T] {
+        interval
+            .index_mut(self)
+            .expect("Interval out of slice bounds")
+    }
+}
+
+/// Positional order: by `start`, then by length. So sorted disjoint intervals are in
+/// address order. This differs from order by length, used inside `IntervalsCollection`.
+impl PartialOrd for Interval {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Interval {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&self.start, &other.start).then(Ord::cmp(&self.len(), &other.len()))
+    }
+}
+
+/// Iterator over integers of `Interval`, returned by `Interval::iter`.
 pub struct IntervalIterator<'a> {
-    interval: &'a Interval,
-    counter: u64,
+    /// Integers of `[front; back)` are not yielded yet.
+    front: u64,
+    back: u64,
+    interval: PhantomData<&'a Interval>,
 }
 
 impl<'a> Iterator for IntervalIterator<'a> {
     type Item = u64;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let result = match self.counter < self.interval.len() {
-            true => Some(self.counter),
-            false => None,
-        };
-        self.counter += 1;
-        result
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        Some(self.front - 1)
+    }
+
+    /// Exact, if count of remaining integers fits into `usize`.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match usize::try_from(self.back - self.front) {
+            Ok(len) => (len, Some(len)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for IntervalIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        Some(self.back)
+    }
+}
+
+impl<'a> FusedIterator for IntervalIterator<'a> {}
+
+/// Iterator returned by `Interval::chunks`.
+pub struct Chunks {
+    rest: Interval,
+    chunk_len: u64,
+}
+
+impl Iterator for Chunks {
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (chunk, rest) = self.rest.split(cmp::min(self.chunk_len, self.rest.len()));
+        self.rest = rest;
+        Some(chunk)
+    }
+}
+
+/// Iterator returned by `Interval::chunks_exact`.
+pub struct ChunksExact {
+    chunks: Chunks,
+    remainder: Interval,
+}
+
+impl ChunksExact {
+    /// Integers after the last chunk, shorter than chunk length. May be empty.
+    pub fn remainder(&self) -> Interval {
+        self.remainder
+    }
+}
+
+impl Iterator for ChunksExact {
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chunks.next()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::interval::Interval;
+    use crate::interval::{self, Interval, IntervalError, IntervalRelation, SplitError};
+    use std::cmp::Ordering;
+    use std::ops::{Bound, Range, RangeBounds};
+
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
 
     #[test]
     fn connect() {
@@ -129,6 +986,393 @@ mod tests {
         assert_eq!(s2.start(), s1.end())
     }
 
+    #[test]
+    fn try_split() {
+        let i = Interval::new(10, 10);
+        assert_eq!(i.try_split(3), Some(i.split(3)));
+        assert_eq!(i.try_split(10), Some((i, Interval::new(20, 0))));
+        assert_eq!(i.try_split(0), Some((Interval::new(10, 0), i)));
+        assert_eq!(i.try_split(11), None);
+        assert_eq!(i.try_split(u64::MAX), None);
+    }
+
+    #[test]
+    fn split_at() {
+        let i = Interval::new(10, 10);
+        let (left, right) = i.split_at(13).unwrap();
+        assert_eq!(left, Interval::new(10, 3));
+        assert_eq!(right, Interval::new(13, 7));
+
+        assert_eq!(i.split_at(10), Ok((Interval::new(10, 0), i)));
+        assert_eq!(i.split_at(20), Ok((i, Interval::new(20, 0))));
+        assert_eq!(i.split_at(9), Err(SplitError::BeforeStart));
+        assert_eq!(i.split_at(21), Err(SplitError::AfterEnd));
+    }
+
+    #[test]
+    fn iter() {
+        let i = Interval::new(10, 3);
+        assert_eq!(i.iter().collect::<Vec<_>>(), [10, 11, 12]);
+        assert_eq!(i.iter().rev().collect::<Vec<_>>(), [12, 11, 10]);
+        assert_eq!(i.iter().size_hint(), (3, Some(3)));
+
+        let mut it = i.iter();
+        assert_eq!(it.next(), Some(10));
+        assert_eq!(it.next_back(), Some(12));
+        assert_eq!(it.size_hint(), (1, Some(1)));
+        assert_eq!(it.next(), Some(11));
+        assert_eq!(it.next_back(), None);
+        assert_eq!(it.next(), None);
+
+        let top = Interval::new(u64::MAX - 2, 2);
+        assert_eq!(top.iter().collect::<Vec<_>>(), [u64::MAX - 2, u64::MAX - 1]);
+        assert_eq!(Interval::new(5, 0).iter().next(), None);
+    }
+
+    #[test]
+    fn chunks() {
+        let i = Interval::new(10, 10);
+        let chunks: Vec<_> = i.chunks(4).collect();
+        let expected = [
+            Interval::new(10, 4),
+            Interval::new(14, 4),
+            Interval::new(18, 2),
+        ];
+        assert_eq!(chunks, expected);
+        assert_eq!(i.chunks(10).collect::<Vec<_>>(), [i]);
+        assert_eq!(i.chunks(100).collect::<Vec<_>>(), [i]);
+        assert_eq!(Interval::new(5, 0).chunks(4).count(), 0);
+
+        let mut exact = i.chunks_exact(4);
+        assert_eq!(exact.remainder(), Interval::new(18, 2));
+        assert_eq!(exact.by_ref().collect::<Vec<_>>(), expected[..2]);
+        assert_eq!(i.chunks_exact(5).remainder(), Interval::new(20, 0));
+        assert_eq!(i.chunks_exact(11).count(), 0);
+
+        let top = Interval::new(u64::MAX - 5, 5);
+        assert_eq!(top.chunks(2).last(), Some(Interval::new(u64::MAX - 1, 1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn chunks_zero() {
+        let _ = Interval::new(0, 10).chunks(0);
+    }
+
+    #[test]
+    fn ord() {
+        let a = Interval::new(0, 10);
+        let b = Interval::new(0, 20);
+        let c = Interval::new(5, 1);
+        let d = Interval::new(30, 0);
+        let mut shuffled = vec![c, d, b, a, c];
+        shuffled.sort();
+        assert_eq!(shuffled, [a, b, c, c, d]);
+
+        assert_eq!(a.cmp(&Interval::new(0, 10)), Ordering::Equal);
+        assert_eq!(a.partial_cmp(&b), Some(Ordering::Less));
+        assert!(Interval::new(1, 0) > Interval::new(0, 100));
+    }
+
+    #[test]
+    fn last() {
+        assert_eq!(Interval::new(10, 3).last(), Some(12));
+        assert_eq!(Interval::new(10, 1).end_inclusive(), Some(10));
+        assert_eq!(Interval::new(10, 0).last(), None);
+        assert_eq!(Interval::new(0, 0).end_inclusive(), None);
+        let top = Interval::new(u64::MAX - 3, 3);
+        assert_eq!(top.last(), Some(u64::MAX - 1));
+    }
+
+    #[test]
+    fn extend() {
+        let i = Interval::new(10, 5);
+        assert_eq!(i.extend_to_contain(12), i);
+        assert_eq!(i.extend_to_contain(15), Interval::new(10, 6));
+        assert_eq!(i.extend_to_contain(3), Interval::new(3, 12));
+        assert_eq!(
+            Interval::new(0, 0).extend_to_contain(7),
+            Interval::new(7, 1)
+        );
+        assert_eq!(i.checked_extend_to_contain(u64::MAX), None);
+        assert_eq!(
+            i.checked_extend_to_contain(u64::MAX - 1),
+            Some(Interval::new(10, u64::MAX - 10))
+        );
+
+        let empty = Interval::new(100, 0);
+        assert_eq!(i.extend_to_cover(&empty), i);
+        assert_eq!(empty.extend_to_cover(&i), i);
+        assert_eq!(
+            i.extend_to_cover(&Interval::new(20, 2)),
+            Interval::new(10, 12)
+        );
+    }
+
+    #[test]
+    fn grow_shrink() {
+        let i = Interval::new(10, 5);
+        assert_eq!(i.grow(3, 4), Some(Interval::new(7, 12)));
+        assert_eq!(i.grow(10, 0), Some(Interval::new(0, 15)));
+        assert_eq!(i.grow(11, 0), None);
+        assert_eq!(
+            i.grow(0, u64::MAX - 15),
+            Some(Interval::new(10, u64::MAX - 10))
+        );
+        assert_eq!(i.grow(0, u64::MAX - 14), None);
+        assert_eq!(Interval::new(5, 0).grow(1, 1), Some(Interval::new(4, 2)));
+
+        let bounds = Interval::new(8, 10);
+        assert_eq!(i.grow_clamped(1, 1, &bounds), Interval::new(9, 7));
+        assert_eq!(i.grow_clamped(5, 5, &bounds), bounds);
+        assert_eq!(
+            i.grow_clamped(20, u64::MAX, &Interval::new(0, u64::MAX)),
+            Interval::new(0, u64::MAX)
+        );
+        let outside = Interval::new(12, 2);
+        assert_eq!(i.grow_clamped(4, 4, &outside), Interval::new(10, 5));
+        assert_eq!(i.grow_clamped(u64::MAX, u64::MAX, &Interval::new(0, 0)), i);
+
+        assert_eq!(i.shrink(1, 2), Some(Interval::new(11, 2)));
+        assert_eq!(i.shrink(2, 3), Some(Interval::new(12, 0)));
+        assert_eq!(i.shrink(3, 3), None);
+        assert_eq!(i.shrink(u64::MAX, 0), None);
+        assert_eq!(i.grow(2, 3).and_then(|g| g.shrink(2, 3)), Some(i));
+    }
+
+    #[test]
+    fn extend_random() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..500 {
+            let start = xorshift(&mut state) % 64;
+            let i = Interval::new(start, xorshift(&mut state) % 8);
+            let p = xorshift(&mut state) % 80;
+            let extended = i.extend_to_contain(p);
+            assert!(extended.contains(p));
+            assert!(extended.contains_interval(&i));
+            let low = i.iter().chain(Some(p)).min().unwrap();
+            let high = i.iter().chain(Some(p)).max().unwrap();
+            assert_eq!(extended.len(), high - low + 1);
+        }
+    }
+
+    #[test]
+    fn gap_to() {
+        let i = Interval::new(10, 10);
+        assert_eq!(i.gap_to(&Interval::new(25, 5)), Some(5));
+        assert_eq!(Interval::new(25, 5).gap_to(&i), Some(5));
+        assert_eq!(i.gap_to(&Interval::new(20, 5)), Some(0));
+        assert_eq!(Interval::new(0, 10).gap_to(&i), Some(0));
+        assert_eq!(i.gap_to(&Interval::new(19, 5)), None);
+        assert_eq!(i.gap_to(&i), None);
+        assert_eq!(i.gap_to(&Interval::new(0, u64::MAX)), None);
+        assert_eq!(
+            Interval::new(0, 1).gap_to(&Interval::new(u64::MAX - 1, 1)),
+            Some(u64::MAX - 2)
+        );
+    }
+
+    #[test]
+    fn relative_to() {
+        let base = Interval::new(100, 50);
+        let child = Interval::new(110, 20);
+        let local = child.relative_to(&base).unwrap();
+        assert_eq!(local, Interval::new(10, 20));
+        assert_eq!(local.rebase_onto(&base), Some(child));
+        assert_eq!(base.relative_to(&base), Some(Interval::new(0, 50)));
+        assert_eq!(Interval::new(90, 20).relative_to(&base), None);
+        assert_eq!(Interval::new(140, 11).relative_to(&base), None);
+
+        assert_eq!(Interval::new(40, 11).rebase_onto(&base), None);
+        assert_eq!(
+            Interval::new(50, 0).rebase_onto(&base),
+            Some(Interval::new(150, 0))
+        );
+        let top = Interval::new(u64::MAX - 10, 10);
+        assert_eq!(
+            Interval::new(5, 5).rebase_onto(&top),
+            Some(Interval::new(u64::MAX - 5, 5))
+        );
+        assert_eq!(Interval::new(5, 6).rebase_onto(&top), None);
+    }
+
+    #[test]
+    fn index() {
+        let mut data = [0, 1, 2, 3, 4, 5];
+        let i = Interval::new(2, 3);
+        assert_eq!(i.index(&data), Some(&[2, 3, 4][..]));
+        assert_eq!(&data[..][i], [2, 3, 4]);
+        assert_eq!(Interval::new(6, 0).index(&data), Some(&[][..]));
+        assert_eq!(Interval::new(4, 3).index(&data), None);
+        assert_eq!(Interval::new(7, 0).index(&data), None);
+
+        i.index_mut(&mut data).unwrap()[0] = 20;
+        data[..][Interval::new(0, 1)][0] = 10;
+        assert_eq!(data, [10, 1, 20, 3, 4, 5]);
+
+        let high = Interval::new(u64::from(u32::MAX) + 1, 1);
+        assert_eq!(high.index(&data), None);
+        assert_eq!(Interval::new(0, u64::MAX).index_mut(&mut data), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        let data = [0, 1, 2];
+        let _ = &data[..][Interval::new(2, 2)];
+    }
+
+    #[test]
+    fn relation() {
+        use IntervalRelation::*;
+        let b = Interval::new(10, 10);
+        let cases = [
+            (Interval::new(0, 5), Before),
+            (Interval::new(5, 5), Meets),
+            (Interval::new(5, 10), Overlaps),
+            (Interval::new(10, 5), Starts),
+            (Interval::new(12, 5), ContainedBy),
+            (Interval::new(15, 5), Finishes),
+            (Interval::new(10, 10), Equal),
+            (Interval::new(5, 15), FinishedBy),
+            (Interval::new(5, 20), Contains),
+            (Interval::new(10, 15), StartedBy),
+            (Interval::new(15, 10), OverlappedBy),
+            (Interval::new(20, 5), MetBy),
+            (Interval::new(25, 5), After),
+            (Interval::new(10, 0), Meets),
+            (Interval::new(20, 0), MetBy),
+            (Interval::new(15, 0), ContainedBy),
+            (Interval::new(10, 1), Starts),
+            (Interval::new(19, 1), Finishes),
+        ];
+        for (a, expected) in cases.iter() {
+            assert_eq!(a.relation(&b), *expected, "{:?}", a);
+            assert_eq!(b.relation(a), expected.converse(), "{:?}", a);
+        }
+        let empty = Interval::new(3, 0);
+        assert_eq!(empty.relation(&empty), Equal);
+    }
+
+    #[test]
+    fn relation_exhaustive() {
+        let all: Vec<_> = (0..6)
+            .flat_map(|start| (0..6).map(move |len| Interval::new(start, len)))
+            .collect();
+        for a in &all {
+            for b in &all {
+                let relation = a.relation(b);
+                assert_eq!(b.relation(a), relation.converse());
+                assert_eq!(relation == IntervalRelation::Equal, a == b);
+
+                let common = a.iter().any(|p| b.contains(p));
+                assert_eq!(a.intersect(b), common, "{:?} {:?}", a, b);
+                let subset = b.iter().all(|p| a.contains(p));
+                assert_eq!(a.contains_interval(b), subset, "{:?} {:?}", a, b);
+                let near = a.start().max(b.start()) <= a.end().min(b.end());
+                assert_eq!(a.near(b), near, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn split_n() {
+        let i = Interval::new(10, 10);
+        let parts: Vec<_> = i.split_n(3).collect();
+        let expected = [
+            Interval::new(10, 4),
+            Interval::new(14, 3),
+            Interval::new(17, 3),
+        ];
+        assert_eq!(parts, expected);
+        assert_eq!(i.split_n(1).collect::<Vec<_>>(), [i]);
+        let parts: Vec<_> = Interval::new(0, 2).split_n(4).collect();
+        let expected = [
+            Interval::new(0, 1),
+            Interval::new(1, 1),
+            Interval::new(2, 0),
+            Interval::new(2, 0),
+        ];
+        assert_eq!(parts, expected);
+
+        let top = Interval::new(1, u64::MAX - 1);
+        let parts: Vec<_> = top.split_n(2).collect();
+        assert_eq!(parts[1].end(), u64::MAX);
+        assert_eq!(parts[0].len() - parts[1].len(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_n_zero() {
+        let _ = Interval::new(0, 10).split_n(0);
+    }
+
+    #[test]
+    fn bisect() {
+        let (a, b) = Interval::new(10, 5).bisect();
+        assert_eq!((a, b), (Interval::new(10, 3), Interval::new(13, 2)));
+        let (a, b) = Interval::new(10, 4).bisect();
+        assert_eq!((a, b), (Interval::new(10, 2), Interval::new(12, 2)));
+        let empty = Interval::new(7, 0);
+        assert_eq!(empty.bisect(), (empty, empty));
+    }
+
+    #[test]
+    fn scale() {
+        let blocks = Interval::new(2, 3);
+        let bytes = blocks.scale(4096).unwrap();
+        assert_eq!(bytes, Interval::new(8192, 12288));
+        assert_eq!(bytes.scale_down(4096), Some(blocks));
+        assert_eq!(blocks.scale(u64::MAX), None);
+        assert_eq!(Interval::new(u64::MAX / 2, 1).scale(2), None);
+
+        let unaligned = Interval::new(8190, 4100);
+        assert_eq!(unaligned.scale_down(4096), None);
+        assert_eq!(Interval::new(8192, 100).scale_down(4096), None);
+        assert_eq!(unaligned.scale_down_lossy(4096), Some(Interval::new(1, 3)));
+        assert_eq!(bytes.scale_down_lossy(4096), Some(blocks));
+        assert_eq!(
+            Interval::new(4100, 0).scale_down_lossy(4096),
+            Some(Interval::new(1, 0))
+        );
+        assert_eq!(bytes.scale_down(0), None);
+        assert_eq!(bytes.scale_down_lossy(0), None);
+    }
+
+    #[test]
+    fn join_within() {
+        let a = Interval::new(0, 10);
+        let b = Interval::new(13, 2);
+        assert!(!a.near_within(&b, 2));
+        assert!(a.near_within(&b, 3));
+        assert!(b.near_within(&a, 3));
+        assert_eq!(a.try_join_within(&b, 2), None);
+        assert_eq!(a.try_join_within(&b, 3), Some(Interval::new(0, 15)));
+        assert!(a.near_within(&Interval::new(5, 10), 0));
+
+        for other in [b, Interval::new(10, 1), Interval::new(3, 1)].iter() {
+            assert_eq!(a.near_within(other, 0), a.near(other));
+            assert_eq!(a.try_join_within(other, 0), a.try_join(other));
+        }
+    }
+
+    #[test]
+    fn empty_semantics() {
+        let empty = Interval::new(5, 0);
+        let i = Interval::new(0, 10);
+        assert!(!empty.contains(5));
+        assert!(!empty.intersect(&i));
+        assert!(!i.intersect(&empty));
+        assert!(!empty.intersect(&empty));
+        assert!(empty.near(&i));
+        assert!(empty.near(&empty));
+        assert!(Interval::new(10, 0).near(&i));
+        assert!(!Interval::new(11, 0).near(&i));
+        assert!(i.contains_interval(&empty));
+        assert!(i.contains_interval(&Interval::new(100, 0)));
+        assert_eq!(i.intersection(&empty), None);
+    }
+
     #[test]
     fn join() {
         let i1 = Interval::new(0, 10);
@@ -144,4 +1388,389 @@ mod tests {
         assert!(i1.try_join(&i3).is_none());
         assert!(i3.try_join(&i1).is_none());
     }
+
+    #[test]
+    fn intersection() {
+        let i = Interval::new(0, 10);
+        assert_eq!(
+            i.intersection(&Interval::new(5, 10)),
+            Some(Interval::new(5, 5))
+        );
+        assert_eq!(
+            Interval::new(5, 10).intersection(&i),
+            Some(Interval::new(5, 5))
+        );
+        assert_eq!(i.intersection(&i), Some(i));
+        assert_eq!(
+            i.intersection(&Interval::new(2, 3)),
+            Some(Interval::new(2, 3))
+        );
+        assert_eq!(i.intersection(&Interval::new(10, 5)), None);
+        assert_eq!(Interval::new(10, 5).intersection(&i), None);
+        assert_eq!(i.intersection(&Interval::new(20, 5)), None);
+        assert_eq!(i.intersection(&Interval::new(3, 0)), None);
+    }
+
+    #[test]
+    fn subtract() {
+        let i = Interval::new(10, 10);
+        let hole = Interval::new(12, 3);
+        let (before, after) = i.subtract(&hole);
+        assert_eq!(before, Some(Interval::new(10, 2)));
+        assert_eq!(after, Some(Interval::new(15, 5)));
+
+        assert_eq!(i.subtract(&i), (None, None));
+        assert_eq!(i.subtract(&Interval::new(0, 100)), (None, None));
+        assert_eq!(i.subtract(&Interval::new(0, 5)), (None, Some(i)));
+        assert_eq!(i.subtract(&Interval::new(30, 5)), (Some(i), None));
+        assert_eq!(i.subtract(&Interval::new(0, 10)), (None, Some(i)));
+        assert_eq!(i.subtract(&Interval::new(20, 5)), (Some(i), None));
+        assert_eq!(
+            i.subtract(&Interval::new(5, 10)),
+            (None, Some(Interval::new(15, 5)))
+        );
+        assert_eq!(
+            i.subtract(&Interval::new(18, 10)),
+            (Some(Interval::new(10, 8)), None)
+        );
+    }
+
+    #[test]
+    fn contains_interval() {
+        let i = Interval::new(10, 10);
+        assert!(i.contains_interval(&i));
+        assert!(i.contains_interval(&Interval::new(10, 3)));
+        assert!(i.contains_interval(&Interval::new(17, 3)));
+        assert!(!i.contains_interval(&Interval::new(17, 4)));
+        assert!(!i.contains_interval(&Interval::new(9, 3)));
+        assert!(!i.contains_interval(&Interval::new(20, 1)));
+        assert!(!i.contains_interval(&Interval::new(5, 20)));
+        assert!(!Interval::new(12, 0).contains_interval(&Interval::new(12, 1)));
+
+        assert!(i.contains_interval(&Interval::new(15, 0)));
+        assert!(i.contains_interval(&Interval::new(100, 0)));
+        assert!(Interval::new(12, 0).contains_interval(&Interval::new(0, 0)));
+    }
+
+    #[test]
+    fn range_conversions() {
+        let i = Interval::new(3, 4);
+        assert_eq!(Interval::from(3u64..7), i);
+        assert_eq!(Interval::from(3..=6), i);
+        assert_eq!(Interval::from(3u32..7), i);
+        assert_eq!(Interval::from(3usize..7), i);
+        assert_eq!(Range::from(i), 3..7);
+        assert_eq!(i.as_range(), 3..7);
+        assert_eq!(i.as_inclusive_range(), Some(3..=6));
+
+        let (high, low) = (7u64, 3u64);
+        assert_eq!(Interval::from(high..low), Interval::new(7, 0));
+        assert_eq!(Interval::from(high..=low), Interval::new(7, 0));
+        assert_eq!(Interval::new(0, 0).as_inclusive_range(), None);
+
+        let top = Interval::from(5..=u64::MAX - 1);
+        assert_eq!(top, Interval::new(5, u64::MAX - 5));
+        assert_eq!(top.as_inclusive_range(), Some(5..=u64::MAX - 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_inclusive_max() {
+        let _ = Interval::from(0..=u64::MAX);
+    }
+
+    #[test]
+    fn align() {
+        assert_eq!(interval::align_up(13, 8), Some(16));
+        assert_eq!(interval::align_up(16, 8), Some(16));
+        assert_eq!(interval::align_up(13, 24), Some(24));
+        assert_eq!(interval::align_up(13, 0), Some(13));
+        assert_eq!(interval::align_up(u64::MAX, 2), None);
+        assert_eq!(interval::align_down(13, 8), 8);
+        assert_eq!(interval::align_down(50, 24), 48);
+        assert_eq!(interval::align_down(13, 0), 13);
+        assert_eq!(interval::align_up(u64::MAX - 7, 8), Some(u64::MAX - 7));
+        assert_eq!(interval::align_up(u64::MAX - 6, 8), None);
+        assert_eq!(interval::align_up(5, 1 << 63), Some(1 << 63));
+        assert_eq!(interval::align_down(u64::MAX, 1 << 63), 1 << 63);
+
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..500 {
+            let value = xorshift(&mut state);
+            let align = 1 << (xorshift(&mut state) % 64);
+            let slow_up = match value % align {
+                0 => Some(value),
+                rem => value.checked_add(align - rem),
+            };
+            assert_eq!(interval::align_up(value, align), slow_up);
+            assert_eq!(interval::align_down(value, align), value - value % align);
+        }
+
+        let i = Interval::new(13, 10);
+        assert_eq!(i.align_start_up(8), Some(Interval::new(16, 7)));
+        assert_eq!(i.align_start_up(1), Some(i));
+        assert_eq!(i.align_start_up(0), Some(i));
+        assert_eq!(i.align_start_up(7), Some(Interval::new(14, 9)));
+        assert_eq!(i.align_start_up(12), None);
+        assert_eq!(i.align_start_up(23), None);
+        assert_eq!(i.align_start_up(1 << 63), None);
+        assert_eq!(i.aligned_len(8), 7);
+        assert_eq!(i.aligned_len(32), 0);
+    }
+
+    #[test]
+    fn normalize() {
+        let input = [
+            Interval::new(20, 5),
+            Interval::new(0, 5),
+            Interval::new(3, 4),
+            Interval::new(7, 1),
+            Interval::new(15, 0),
+            Interval::new(20, 5),
+            Interval::new(30, 2),
+        ];
+        let expected = [
+            Interval::new(0, 8),
+            Interval::new(20, 5),
+            Interval::new(30, 2),
+        ];
+        assert_eq!(interval::normalize(input.iter().copied()), expected);
+        assert!(interval::normalize(vec![Interval::new(5, 0)]).is_empty());
+    }
+
+    #[test]
+    fn normalize_random() {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..200 {
+            let count = xorshift(&mut state) % 8;
+            let input: Vec<_> = (0..count)
+                .map(|_| Interval::new(xorshift(&mut state) % 60, xorshift(&mut state) % 6))
+                .collect();
+            let normalized = interval::normalize(input.clone());
+            for p in 0..70 {
+                let covered = input.iter().any(|i| i.contains(p));
+                assert_eq!(normalized.iter().any(|i| i.contains(p)), covered);
+            }
+            for pair in normalized.windows(2) {
+                assert!(!pair[0].is_empty());
+                assert!(pair[0].end() < pair[1].start());
+            }
+        }
+    }
+
+    #[test]
+    fn placements() {
+        let i = Interval::new(3, 14);
+        let found: Vec<_> = i.placements(4, 4).collect();
+        assert_eq!(found, [4, 8, 12]);
+        let found: Vec<_> = i.placements(12, 1).collect();
+        assert_eq!(found, [3, 4, 5]);
+        let found: Vec<_> = i.placements(14, 1).collect();
+        assert_eq!(found, [3]);
+        assert_eq!(i.placements(15, 1).count(), 0);
+        assert_eq!(i.placements(1, 32).count(), 0);
+        assert_eq!(i.placements(0, 8).collect::<Vec<_>>(), [8, 16]);
+
+        let top = Interval::new(u64::MAX - 10, 10);
+        assert_eq!(top.placements(1, 1 << 63).count(), 0);
+        let found: Vec<_> = top.placements(7, 2).collect();
+        assert_eq!(found, [u64::MAX - 9, u64::MAX - 7]);
+
+        let all = Interval::from_bounds(0, u64::MAX);
+        let mut found = all.placements(0, 1);
+        assert_eq!(found.next(), Some(0));
+        assert_eq!(found.next(), Some(1));
+        assert_eq!(all.placements(0, 1 << 63).collect::<Vec<_>>(), [0, 1 << 63]);
+        assert!(i.placements(1, 0).eq(i.placements(1, 1)));
+    }
+
+    #[test]
+    fn range_bounds() {
+        let i = Interval::new(3, 4);
+        assert_eq!(i.start_bound(), Bound::Included(&3));
+        assert_eq!(i.end_bound(), Bound::Excluded(&7));
+        assert!(RangeBounds::contains(&i, &3));
+        assert!(!RangeBounds::contains(&i, &7));
+
+        let top = Interval::new(u64::MAX - 2, 2);
+        assert_eq!(top.end_bound(), Bound::Excluded(&u64::MAX));
+        assert!(RangeBounds::contains(&top, &(u64::MAX - 1)));
+        assert!(!RangeBounds::contains(&top, &u64::MAX));
+    }
+
+    #[test]
+    fn format() {
+        let i = Interval::new(0x1000, 0x1aff);
+        assert_eq!(format!("{}", i), "4096..11007");
+        assert_eq!(format!("{:x}", i), "1000..2aff");
+        assert_eq!(format!("{:#x}", i), "0x1000..0x2aff");
+        assert_eq!(format!("{:X}", i), "1000..2AFF");
+        assert_eq!(format!("{:#X}", i), "0x1000..0x2AFF");
+        assert_eq!(format!("{:#06x}", Interval::new(0, 16)), "0x0000..0x0010");
+    }
+
+    #[test]
+    fn const_eval() {
+        const I: Interval = Interval::new(10, 5);
+        const B: Interval = Interval::from_bounds(10, 15);
+        const CHECKED: Option<Interval> = Interval::checked_new(u64::MAX, 1);
+        const LEN: u64 = I.len();
+        const END: u64 = B.end();
+        const CONTAINS: bool = I.contains(14) && !I.contains(15);
+        const EMPTY: bool = Interval::new(3, 0).is_empty();
+        assert_eq!(I, B);
+        assert_eq!(CHECKED, None);
+        assert_eq!((I.start(), LEN, END), (10, 5, 15));
+        assert_eq!((CONTAINS, EMPTY), (true, true));
+    }
+
+    #[test]
+    fn from_bounds() {
+        assert_eq!(Interval::from_bounds(5, 15), Interval::new(5, 10));
+        assert_eq!(Interval::from_bounds(5, 5), Interval::new(5, 0));
+        let top = Interval::from_bounds(10, u64::MAX);
+        assert_eq!(top, Interval::new(10, u64::MAX - 10));
+        assert_eq!(top.end(), u64::MAX);
+        assert_eq!(
+            Interval::try_from_bounds(0, u64::MAX),
+            Ok(Interval::new(0, u64::MAX))
+        );
+        assert_eq!(
+            Interval::try_from_bounds(6, 5),
+            Err(IntervalError::InvalidEnd)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_bounds_reversed() {
+        let _ = Interval::from_bounds(6, 5);
+    }
+
+    #[test]
+    fn checked_new() {
+        assert_eq!(Interval::checked_new(5, 10), Some(Interval::new(5, 10)));
+        let top = Interval::checked_new(u64::MAX - 10, 10).unwrap();
+        assert_eq!(top.end(), u64::MAX);
+        assert_eq!(Interval::checked_new(u64::MAX - 10, 11), None);
+        assert_eq!(Interval::checked_new(u64::MAX, u64::MAX), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_overflow() {
+        let _ = Interval::new(u64::MAX - 2, 10);
+    }
+
+    #[test]
+    fn top_arithmetic() {
+        let top = Interval::new(u64::MAX - 10, 10);
+        let huge = Interval::new(1, u64::MAX - 1);
+        assert_eq!(top.connect(&huge), huge);
+        assert!(top.intersect(&huge));
+        assert!(top.near(&huge));
+        assert!(!top.intersect(&Interval::new(0, u64::MAX - 10)));
+        assert!(top.near(&Interval::new(0, u64::MAX - 10)));
+        assert!(!top.near(&Interval::new(0, u64::MAX - 11)));
+
+        let (left, right) = top.split(10);
+        assert_eq!(left, top);
+        assert_eq!(right, Interval::new(u64::MAX, 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_too_long() {
+        let _ = Interval::new(u64::MAX - 10, 10).split(11);
+    }
+
+    #[test]
+    fn try_new() {
+        assert_eq!(Interval::try_new(5, 10), Ok(Interval::new(5, 10)));
+        assert!(Interval::try_new(u64::MAX - 10, 10).is_ok());
+        assert_eq!(
+            Interval::try_new(u64::MAX - 10, 11),
+            Err(IntervalError::Overflow)
+        );
+    }
+
+    #[test]
+    fn shift() {
+        let i = Interval::new(10, 5);
+        assert_eq!(i.shift(5), Some(Interval::new(15, 5)));
+        assert_eq!(i.shift(-10), Some(Interval::new(0, 5)));
+        assert_eq!(i.shift(-11), None);
+        assert_eq!(
+            i.shift_up(u64::MAX - 15),
+            Some(Interval::new(u64::MAX - 5, 5))
+        );
+        assert_eq!(i.shift_up(u64::MAX - 14), None);
+        assert_eq!(i.shift_down(10), Some(Interval::new(0, 5)));
+        assert_eq!(i.shift_down(11), None);
+
+        assert_eq!(i.saturating_shift(-100), Interval::new(0, 5));
+        assert_eq!(
+            i.saturating_shift(i64::MAX),
+            Interval::new(i64::MAX as u64 + 10, 5)
+        );
+        let top = Interval::new(u64::MAX - 10, 5);
+        assert_eq!(top.saturating_shift(10), Interval::new(u64::MAX - 5, 5));
+        assert_eq!(top.shift(6), None);
+    }
+
+    #[test]
+    fn setters() {
+        let mut i = Interval::new(0, 10);
+        i.set_start(5).unwrap();
+        assert_eq!(i, Interval::new(5, 10));
+        i.set_len(3).unwrap();
+        assert_eq!(i, Interval::new(5, 3));
+
+        assert_eq!(i.set_start(u64::MAX - 2), Err(IntervalError::Overflow));
+        assert_eq!(i.set_len(u64::MAX), Err(IntervalError::Overflow));
+        assert_eq!(i, Interval::new(5, 3));
+    }
+
+    #[test]
+    fn grow_and_shrink() {
+        let mut i = Interval::new(5, 5);
+        i.grow_to(20).unwrap();
+        assert_eq!(i, Interval::new(5, 15));
+        assert_eq!(i.grow_to(19), Err(IntervalError::InvalidEnd));
+
+        i.shrink_to(6).unwrap();
+        assert_eq!(i, Interval::new(5, 1));
+        i.shrink_to(5).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(i.shrink_to(4), Err(IntervalError::InvalidEnd));
+        assert_eq!(i.shrink_to(6), Err(IntervalError::InvalidEnd));
+        assert_eq!(i, Interval::new(5, 0));
+    }
+
+    #[test]
+    fn map_point() {
+        let old = Interval::new(100, 10);
+        let new = Interval::new(20, 5);
+        assert_eq!(old.map_point(100, &new), Some(20));
+        assert_eq!(old.map_point(104, &new), Some(24));
+        assert_eq!(old.map_point(105, &new), None);
+        assert_eq!(old.map_point(99, &new), None);
+        assert_eq!(old.map_point(110, &new), None);
+    }
+
+    #[test]
+    fn map_interval() {
+        let old = Interval::new(100, 10);
+        let new = Interval::new(20, 5);
+        let sub = Interval::new(101, 3);
+        assert_eq!(old.map_interval(&sub, &new), Some(Interval::new(21, 3)));
+        assert_eq!(
+            old.map_interval(&Interval::new(102, 3), &new),
+            Some(Interval::new(22, 3))
+        );
+        assert_eq!(old.map_interval(&Interval::new(103, 3), &new), None);
+        assert_eq!(old.map_interval(&Interval::new(99, 3), &new), None);
+        assert_eq!(old.map_interval(&Interval::new(108, 3), &old), None);
+        assert_eq!(old.map_interval(&old, &old), Some(old));
+    }
 }
@@ -0,0 +1,330 @@
+//! Lazy set operations over streams of intervals, sorted by start.
+//!
+//! Inputs must be sorted by start and must not overlap (adjacent intervals are allowed).
+//! Outputs are sorted by start and contain no empty intervals.
+
+use crate::interval::Interval;
+use std::cmp;
+use std::iter::Peekable;
+
+/// Integers contained in `a` or in `b`. Near intervals are joined.
+pub fn union<A, B>(a: A, b: B) -> Union<A::IntoIter, B::IntoIter>
+where
+    A: IntoIterator<Item = Interval>,
+    B: IntoIterator<Item = Interval>,
+{
+    Union {
+        a: a.into_iter().peekable(),
+        b: b.into_iter().peekable(),
+    }
+}
+
+/// Integers contained both in `a` and in `b`.
+pub fn intersection<A, B>(a: A, b: B) -> Intersection<A::IntoIter, B::IntoIter>
+where
+    A: IntoIterator<Item = Interval>,
+    B: IntoIterator<Item = Interval>,
+{
+    Intersection {
+        a: a.into_iter().peekable(),
+        b: b.into_iter().peekable(),
+    }
+}
+
+/// Integers contained in `a`, but not in `b`.
+pub fn difference<A, B>(a: A, b: B) -> Difference<A::IntoIter, B::IntoIter>
+where
+    A: IntoIterator<Item = Interval>,
+    B: IntoIterator<Item = Interval>,
+{
+    Difference {
+        a: a.into_iter(),
+        b: b.into_iter().peekable(),
+        current: None,
+    }
+}
+
+/// Maximal subintervals of `bounds`, which don't intersect with any interval of `iter`.
+pub fn gaps<I>(iter: I, bounds: Interval) -> Gaps<I::IntoIter>
+where
+    I: IntoIterator<Item = Interval>,
+{
+    Gaps {
+        iter: iter.into_iter(),
+        bounds,
+        cursor: bounds.start(),
+    }
+}
+
+/// Iterator returned by [`union`].
+pub struct Union<A: Iterator<Item = Interval>, B: Iterator<Item = Interval>> {
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<A, B> Union<A, B>
+where
+    A: Iterator<Item = Interval>,
+    B: Iterator<Item = Interval>,
+{
+    /// Next non-empty interval with the least start among both streams.
+    fn next_least(&mut self) -> Option<Interval> {
+        self.skip_empty();
+        let take_a = match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) => a.start() <= b.start(),
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return None,
+        };
+        match take_a {
+            true => self.a.next(),
+            false => self.b.next(),
+        }
+    }
+
+    fn skip_empty(&mut self) {
+        while self.a.peek().is_some_and(|a| a.is_empty()) {
+            self.a.next();
+        }
+        while self.b.peek().is_some_and(|b| b.is_empty()) {
+            self.b.next();
+        }
+    }
+}
+
+impl<A, B> Iterator for Union<A, B>
+where
+    A: Iterator<Item = Interval>,
+    B: Iterator<Item = Interval>,
+{
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut current = self.next_least()?;
+        loop {
+            self.skip_empty();
+            let a_near = self.a.peek().is_some_and(|a| a.start() <= current.end());
+            let b_near = self.b.peek().is_some_and(|b| b.start() <= current.end());
+            if !a_near && !b_near {
+                return Some(current);
+            }
+            if let Some(next) = self.next_least() {
+                current = current.connect(&next);
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`intersection`].
+pub struct Intersection<A: Iterator<Item = Interval>, B: Iterator<Item = Interval>> {
+    a: Peekable<A>,
+    b: Peekable<B>,
+}
+
+impl<A, B> Iterator for Intersection<A, B>
+where
+    A: Iterator<Item = Interval>,
+    B: Iterator<Item = Interval>,
+{
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let a = *self.a.peek()?;
+            let b = *self.b.peek()?;
+            let start = cmp::max(a.start(), b.start());
+            let end = cmp::min(a.end(), b.end());
+            match a.end() <= b.end() {
+                true => self.a.next(),
+                false => self.b.next(),
+            };
+            if start < end {
+                return Some(Interval::from_bounds(start, end));
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`difference`].
+pub struct Difference<A: Iterator<Item = Interval>, B: Iterator<Item = Interval>> {
+    a: A,
+    b: Peekable<B>,
+    current: Option<Interval>,
+}
+
+impl<A, B> Iterator for Difference<A, B>
+where
+    A: Iterator<Item = Interval>,
+    B: Iterator<Item = Interval>,
+{
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let current = match self.current.take() {
+                Some(current) => current,
+                None => self.a.next()?,
+            };
+            if current.is_empty() {
+                continue;
+            }
+            while self
+                .b
+                .peek()
+                .is_some_and(|b| b.is_empty() || b.end() <= current.start())
+            {
+                self.b.next();
+            }
+            let b = match self.b.peek() {
+                Some(b) if b.start() < current.end() => *b,
+                _ => return Some(current),
+            };
+
+            if b.end() < current.end() {
+                self.current = Some(Interval::from_bounds(b.end(), current.end()));
+            }
+            if b.start() > current.start() {
+                return Some(Interval::from_bounds(current.start(), b.start()));
+            }
+        }
+    }
+}
+
+/// Iterator returned by [`gaps`].
+pub struct Gaps<I: Iterator<Item = Interval>> {
+    iter: I,
+    bounds: Interval,
+    cursor: u64,
+}
+
+impl<I: Iterator<Item = Interval>> Iterator for Gaps<I> {
+    type Item = Interval;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.bounds.end();
+        while self.cursor < end {
+            let gap_start = self.cursor;
+            let gap_end = match self.iter.next() {
+                Some(int) if int.is_empty() => continue,
+                Some(int) => {
+                    self.cursor = cmp::max(self.cursor, cmp::min(int.end(), end));
+                    cmp::min(int.start(), end)
+                }
+                None => {
+                    self.cursor = end;
+                    end
+                }
+            };
+            if gap_end > gap_start {
+                return Some(Interval::from_bounds(gap_start, gap_end));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::ops;
+    use crate::interval::Interval;
+
+    const BITS: u64 = 128;
+
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    /// Random sorted disjoint intervals, which may be adjacent or empty.
+    fn random_intervals(state: &mut u64) -> Vec<Interval> {
+        let mut result = Vec::new();
+        let mut pos = xorshift(state) % 8;
+        while pos < BITS {
+            let len = xorshift(state) % 12;
+            let len = std::cmp::min(len, BITS - pos);
+            result.push(Interval::new(pos, len));
+            pos += len + xorshift(state) % 6;
+        }
+        result
+    }
+
+    fn bitmap(intervals: &[Interval]) -> u128 {
+        intervals
+            .iter()
+            .flat_map(|i| i.start()..i.end())
+            .fold(0, |map, p| map | 1 << p)
+    }
+
+    fn assert_normalized(intervals: &[Interval]) {
+        assert!(intervals.iter().all(|i| !i.is_empty()));
+        for pair in intervals.windows(2) {
+            assert!(pair[0].end() < pair[1].start());
+        }
+    }
+
+    fn check(
+        op: impl Fn(&[Interval], &[Interval]) -> Vec<Interval>,
+        model: impl Fn(u128, u128) -> u128,
+    ) {
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..1000 {
+            let a = random_intervals(&mut state);
+            let b = random_intervals(&mut state);
+            let result = op(&a, &b);
+            assert_eq!(bitmap(&result), model(bitmap(&a), bitmap(&b)));
+        }
+    }
+
+    #[test]
+    fn union() {
+        check(
+            |a, b| {
+                let result: Vec<_> = ops::union(a.iter().copied(), b.iter().copied()).collect();
+                assert_normalized(&result);
+                result
+            },
+            |a, b| a | b,
+        );
+    }
+
+    #[test]
+    fn intersection() {
+        check(
+            |a, b| ops::intersection(a.iter().copied(), b.iter().copied()).collect(),
+            |a, b| a & b,
+        );
+    }
+
+    #[test]
+    fn difference() {
+        check(
+            |a, b| ops::difference(a.iter().copied(), b.iter().copied()).collect(),
+            |a, b| a & !b,
+        );
+    }
+
+    #[test]
+    fn gaps() {
+        let bounds = Interval::new(10, 100);
+        let bounds_map = bitmap(&[bounds]);
+        check(
+            |a, _| {
+                let result: Vec<_> = ops::gaps(a.iter().copied(), bounds).collect();
+                assert_normalized(&result);
+                result
+            },
+            |a, _| bounds_map & !a,
+        );
+    }
+
+    #[test]
+    fn gaps_edges() {
+        let bounds = Interval::new(0, 10);
+        let none: Vec<Interval> = Vec::new();
+        assert_eq!(ops::gaps(none, bounds).collect::<Vec<_>>(), vec![bounds]);
+        let full = vec![Interval::new(0, 20)];
+        assert!(ops::gaps(full, bounds).next().is_none());
+    }
+}
@@ -1,50 +1,1108 @@
+pub mod chained;
 pub mod collection;
+pub mod frozen;
 pub mod interval;
+pub mod multi;
+pub mod signed;
+pub mod stats;
+pub mod typed;
 
-use crate::collection::IntervalsCollection;
+use crate::collection::{
+    InsertError, IntervalsCollection, IntervalsSnapshot, RebaseError, SplitPlacement,
+    StateHasher, Strategy,
+};
+use crate::frozen::FrozenSubranges;
 use crate::interval::Interval;
+use crate::stats::StatsHandle;
+use std::error::Error;
+use std::fmt;
 
 /// Provides non-intersecting integer subranges of initial range.
 pub struct Subranges {
     free: IntervalsCollection,
-    len: u64,
+    range: Interval,
+    guard: u64,
+    guard_len: u64,
+    reserve: u64,
+    watchpoints: Vec<Watchpoint>,
+    next_watchpoint: u64,
+    stats: StatsHandle,
 }
 
+struct Watchpoint {
+    token: WatchpointToken,
+    range: Interval,
+    callback: Box<dyn FnMut(&Interval) + Send>,
+}
+
+/// Identifies watchpoint, added by `Subranges::add_watchpoint`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WatchpointToken(u64);
+
 impl Subranges {
 
     /// Creates `Self` with specified free range.
     pub fn new(range: Interval) -> Self {
+        Self::with_guard(range, 0)
+    }
+
+    /// Creates `Self` with specified free range.
+    /// Every take reserves `guard` integers after returned interval. They stay
+    /// unavailable until the interval is erased.
+    pub fn with_guard(range: Interval, guard: u64) -> Self {
         let mut free = IntervalsCollection::default();
         free.insert(range);
-        Self { free, len: range.len() }
+        Self {
+            free,
+            range,
+            guard,
+            guard_len: 0,
+            reserve: 0,
+            watchpoints: Vec::new(),
+            next_watchpoint: 0,
+            stats: StatsHandle::new(range.len(), range.len()),
+        }
     }
 
     /// Take free interval with specified `length` and returns it.
     /// If free interval with specified `length` doesn't exists, return None.
+    /// Also return None if taking it would leave less than `reserve()` free.
+    /// Empty intervals are never taken: return None if `length` is 0.
     pub fn take_free_subrange(&mut self, length: u64) -> Option<Interval> {
-        assert!(length > 0, "Length must be > 0");
-        self.free.take_exact(length)
+        if length == 0 {
+            return None;
+        }
+        let int = self
+            .guarded_len(length)
+            .filter(|l| self.keeps_reserve(*l))
+            .and_then(|guarded| {
+                let reserve = self.reserve;
+                self.free
+                    .take_leaving(reserve, |free| free.take_exact(guarded))
+                    .and_then(Result::ok)
+            });
+        self.finish_take(int)
     }
 
     /// Take free interval with specified `length` and returns it.
     /// If free interval with specified `length` doesn't exists, return None.
+    /// Also return None if taking it would leave less than `reserve()` free.
+    /// `align` 0 and 1 mean no alignment, any other `align` is supported.
+    /// If `min_remainder()` isn't 0, returned interval may start below a multiple of
+    /// `align`: it includes short free parts before and after aligned placement.
     pub fn take_free_align_subrange(&mut self, length: u64, align: u64) -> Option<Interval> {
-        assert!(length > 0, "Length must be > 0");
-        self.free.take_exact_aligned(length, align)
+        if length == 0 {
+            return None;
+        }
+        let int = self
+            .guarded_len(length)
+            .filter(|l| self.keeps_reserve(*l))
+            .and_then(|guarded| {
+                let reserve = self.reserve;
+                self.free
+                    .take_leaving(reserve, |free| free.take_exact_aligned(guarded, align))
+                    .and_then(Result::ok)
+            });
+        self.finish_take(int)
+    }
+
+    /// Take the longest free interval, see `IntervalsCollection::peek_max`.
+    /// Return None if it doesn't exceed guard or taking it would leave less than
+    /// `reserve()` free.
+    pub fn take_max_subrange(&mut self) -> Option<Interval> {
+        let int = self
+            .free
+            .peek_max()
+            .filter(|max| max.len() > self.guard && self.keeps_reserve(max.len()))
+            .and_then(|_| self.free.take_max());
+        self.finish_take(int)
+    }
+
+    /// Same as `take_free_align_subrange`, but may use reserved free space.
+    pub fn take_from_reserve(&mut self, length: u64, align: u64) -> Option<Interval> {
+        if length == 0 {
+            return None;
+        }
+        let int = self
+            .guarded_len(length)
+            .and_then(|guarded| self.free.take_exact_aligned(guarded, align));
+        self.finish_take(int)
+    }
+
+    /// Same as `take_free_align_subrange`, but neither taken interval nor its guard
+    /// intersect with any of `excluded` intervals. Nothing is reserved by `excluded`.
+    pub fn take_free_excluding_subrange(
+        &mut self,
+        length: u64,
+        align: u64,
+        excluded: &[Interval],
+    ) -> Option<Interval> {
+        if length == 0 {
+            return None;
+        }
+        let int = self
+            .guarded_len(length)
+            .filter(|l| self.keeps_reserve(*l))
+            .and_then(|guarded| {
+                let reserve = self.reserve;
+                self.free
+                    .take_leaving(reserve, |free| {
+                        free.take_exact_excluding(guarded, align, excluded)
+                    })
+                    .and_then(Result::ok)
+            });
+        self.finish_take(int)
+    }
+
+    /// Find placement for all `requests` of `(length, align)` in current free space,
+    /// without taking anything. Longest requests are placed first. Return intervals
+    /// in order of requests, or `None` if some request doesn't fit.
+    pub fn plan_allocations(&self, requests: &[(u64, u64)]) -> Option<Vec<Interval>> {
+        let mut guarded = Vec::with_capacity(requests.len());
+        for &(length, align) in requests {
+            if length == 0 {
+                return None;
+            }
+            guarded.push((self.guarded_len(length)?, align));
+        }
+        let total = guarded
+            .iter()
+            .try_fold(0u64, |total, (length, _)| total.checked_add(*length))?;
+        if !self.keeps_reserve(total) {
+            return None;
+        }
+
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(guarded[i].0));
+        let mut free = self.free.clone();
+        free.set_strategy(Strategy::BestFit);
+        free.set_placement(SplitPlacement::Front);
+        free.set_min_remainder(0);
+        let mut plan = vec![Interval::new(0, 0); requests.len()];
+        for i in order {
+            let (length, align) = guarded[i];
+            let int = free.take_exact_aligned(length, align)?;
+            plan[i] = Interval::new(int.start(), requests[i].0);
+        }
+        Some(plan)
+    }
+
+    /// Take all intervals of `plan` (as returned by `plan_allocations`) at once.
+    /// If some of them isn't free, take nothing.
+    pub fn commit_plan(&mut self, plan: &[Interval]) -> Result<(), SubrangeError> {
+        let res = self.try_commit_plan(plan);
+        if res.is_err() {
+            self.stats.record_failed_take();
+        }
+        res
+    }
+
+    fn try_commit_plan(&mut self, plan: &[Interval]) -> Result<(), SubrangeError> {
+        let mut free = self.free.clone();
+        let mut total = 0u64;
+        for subrange in plan {
+            let guarded = self
+                .guarded_len(subrange.len())
+                .and_then(|len| Interval::try_new(subrange.start(), len).ok())
+                .filter(|guarded| free.carve_at(*guarded))
+                .ok_or(SubrangeError::NotFree(*subrange))?;
+            total = total.saturating_add(guarded.len());
+        }
+        if !self.keeps_reserve(total) {
+            return Err(SubrangeError::WouldBreachReserve);
+        }
+        self.free = free;
+        self.guard_len += self.guard * plan.len() as u64;
+        self.publish_stats();
+        for subrange in plan {
+            self.notify_watchpoints(subrange);
+        }
+        Ok(())
+    }
+
+    /// Keep at least `reserve` integers free for `take_from_reserve`.
+    pub fn set_reserve(&mut self, reserve: u64) {
+        self.reserve = reserve;
+    }
+
+    /// Free space, which ordinary takes leave untouched.
+    pub fn reserve(&self) -> u64 {
+        self.reserve
+    }
+
+    /// Lock-free view of free and used lengths, which can be read from other threads.
+    pub fn stats(&self) -> StatsHandle {
+        self.stats.clone()
+    }
+
+    fn publish_stats(&self) {
+        self.stats.publish(self.free_len(), self.len());
+    }
+
+    fn guarded_len(&self, length: u64) -> Option<u64> {
+        length.checked_add(self.guard)
+    }
+
+    /// True if taking `length` integers leaves at least `reserve` free.
+    fn keeps_reserve(&self, length: u64) -> bool {
+        self.free_len()
+            .checked_sub(length)
+            .is_some_and(|left| left >= self.reserve)
+    }
+
+    /// Take free interval with specified `length` and `align`, which doesn't cross
+    /// any multiple of `boundary`. Guard must fit into the same window.
+    pub fn take_free_no_cross_subrange(
+        &mut self,
+        length: u64,
+        align: u64,
+        boundary: u64,
+    ) -> Result<Interval, NoCrossError> {
+        if length == 0 {
+            return Err(NoCrossError::NoFreeSubrange);
+        }
+        match self.try_take_no_cross(length, align, boundary) {
+            Ok(int) => Ok(self.split_guard(int)),
+            Err(e) => {
+                self.stats.record_failed_take();
+                Err(e)
+            }
+        }
+    }
+
+    fn try_take_no_cross(
+        &mut self,
+        length: u64,
+        align: u64,
+        boundary: u64,
+    ) -> Result<Interval, NoCrossError> {
+        let guarded = self
+            .guarded_len(length)
+            .filter(|guarded| *guarded <= boundary)
+            .ok_or(NoCrossError::LongerThanBoundary)?;
+        if !self.keeps_reserve(guarded) {
+            return Err(NoCrossError::WouldBreachReserve);
+        }
+        let reserve = self.reserve;
+        let taken = self.free.take_leaving(reserve, |free| {
+            free.take_exact_no_cross(guarded, align, boundary)
+        });
+        match taken {
+            Some(Ok(int)) => Ok(int),
+            Some(Err(_)) => Err(NoCrossError::WouldBreachReserve),
+            None => Err(NoCrossError::NoFreeSubrange),
+        }
+    }
+
+    /// Split guard off taken `int`, or count failed take.
+    fn finish_take(&mut self, int: Option<Interval>) -> Option<Interval> {
+        match int {
+            Some(int) => Some(self.split_guard(int)),
+            None => {
+                self.stats.record_failed_take();
+                None
+            }
+        }
+    }
+
+    /// Cut guard off the end of taken `int`.
+    /// Every take ends here, so watchpoints are notified here.
+    fn split_guard(&mut self, int: Interval) -> Interval {
+        self.guard_len += self.guard;
+        let split = int.try_split(int.len() - self.guard);
+        debug_assert!(split.is_some(), "Taken interval must include guard");
+        let taken = split.map_or(int, |(taken, _)| taken);
+        self.publish_stats();
+        self.notify_watchpoints(&taken);
+        taken
+    }
+
+    /// Call `callback` with every taken interval, which intersects with `range`.
+    pub fn add_watchpoint(
+        &mut self,
+        range: Interval,
+        callback: impl FnMut(&Interval) + Send + 'static,
+    ) -> WatchpointToken {
+        let token = WatchpointToken(self.next_watchpoint);
+        self.next_watchpoint += 1;
+        self.watchpoints.push(Watchpoint {
+            token,
+            range,
+            callback: Box::new(callback),
+        });
+        token
+    }
+
+    /// Remove watchpoint. Return `false` if it was already removed.
+    pub fn remove_watchpoint(&mut self, token: WatchpointToken) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.token != token);
+        self.watchpoints.len() != len
+    }
+
+    fn notify_watchpoints(&mut self, taken: &Interval) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        for watchpoint in &mut self.watchpoints {
+            if watchpoint.range.intersect(taken) {
+                (watchpoint.callback)(taken);
+            }
+        }
+    }
+
+    /// Set policy used to choose free intervals for following takes.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.free.set_strategy(strategy)
+    }
+
+    /// Set where following takes place their result inside chosen free interval.
+    pub fn set_placement(&mut self, placement: SplitPlacement) {
+        self.free.set_placement(placement)
+    }
+
+    /// Restart random generator, used by `Strategy::Random`, from `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.free.set_seed(seed)
+    }
+
+    /// Return free parts shorter than `min_remainder`, which takes would leave around
+    /// taken subranges, as parts of them. See `IntervalsCollection::set_min_remainder`.
+    /// So taken subranges may be longer than requested and start below requested `align`.
+    /// Reserve is still kept: takes fail if these parts would breach it.
+    pub fn set_min_remainder(&mut self, min_remainder: u64) {
+        self.free.set_min_remainder(min_remainder)
+    }
+
+    /// Restart `Strategy::NextFit` searches from the lowest address.
+    pub fn reset_cursor(&mut self) {
+        self.free.reset_cursor()
+    }
+
+    /// Free whole range, as if every taken subrange was erased.
+    /// Settings, watchpoints and stats counters are kept.
+    pub fn reset(&mut self) {
+        self.free.reset(self.range);
+        self.guard_len = 0;
+        self.publish_stats();
     }
 
     /// Free all filled intervals, that intersects with `subrange`.
+    /// If guard is set, `subrange` must be returned by a take: its guard is freed too.
+    /// Erasing an empty interval does nothing.
     pub fn erase_subrange(&mut self, subrange: Interval) {
-        self.free.insert(subrange)
+        if subrange.is_empty() {
+            return;
+        }
+        if self.guard > 0 {
+            self.guard_len = self.guard_len.saturating_sub(self.guard);
+            let guarded = Interval::new(subrange.start(), subrange.len() + self.guard);
+            self.free.insert(guarded);
+        } else {
+            self.free.insert(subrange);
+        }
+        self.publish_stats();
+    }
+
+    /// Same as `erase_subrange`, but fail without changes if `subrange` or its guard
+    /// is already free, e.g. on double erase.
+    pub fn try_erase_subrange(&mut self, subrange: Interval) -> Result<(), InsertError> {
+        if subrange.is_empty() {
+            return Ok(());
+        }
+        let guarded = Interval::new(subrange.start(), subrange.len() + self.guard);
+        self.free.insert_checked(guarded)?;
+        self.guard_len = self.guard_len.saturating_sub(self.guard);
+        self.publish_stats();
+        Ok(())
+    }
+
+    /// Predict result of `erase_subrange(subrange)` without erasing.
+    pub fn simulate_erase(&self, subrange: Interval) -> SimulatedResult {
+        let freed = Interval::new(subrange.start(), subrange.len() + self.guard);
+        let (merged_block, new_largest_free) = self.free.simulate_insert_with_largest(freed);
+        SimulatedResult {
+            merged_block,
+            new_largest_free,
+        }
+    }
+
+    /// Amount of free integers inside `query`.
+    pub fn free_within(&self, query: Interval) -> u64 {
+        self.free.free_within(query)
+    }
+
+    /// Amount of taken integers (guards included) inside `query`.
+    /// Integers outside of full range are neither free nor taken.
+    pub fn used_within(&self, query: Interval) -> u64 {
+        let inside = query.intersection(&self.range).map_or(0, |i| i.len());
+        inside - self.free_within(query)
+    }
+
+    /// Length of guard, reserved after every taken interval.
+    pub fn guard(&self) -> u64 {
+        self.guard
+    }
+
+    /// Total length of guards of taken intervals.
+    pub fn guard_len(&self) -> u64 {
+        self.guard_len
+    }
+
+    /// Total length of alignment pads, split off by aligned takes.
+    pub fn padding_created(&self) -> u64 {
+        self.free.padding_created()
+    }
+
+    /// Take read-only snapshot of current state.
+    pub fn freeze(&self) -> FrozenSubranges {
+        FrozenSubranges::new(self.free.iter_by_position(), self.range)
+    }
+
+    /// Save current state to `restore` it later. Unlike `freeze`, it also saves
+    /// search settings and state, but can't be inspected.
+    pub fn snapshot(&self) -> SubrangesSnapshot {
+        SubrangesSnapshot {
+            range: self.range,
+            free: self.free.snapshot(),
+            guard_len: self.guard_len,
+        }
+    }
+
+    /// Return to state of `snapshot`. Reserve, watchpoints and stats counters are
+    /// kept, watchpoints aren't notified.
+    /// # Panics
+    /// * Panics if `snapshot` was taken from `Subranges` with other full range
+    pub fn restore(&mut self, snapshot: &SubrangesSnapshot) {
+        assert_eq!(snapshot.range, self.range, "Snapshot must be taken from the same range");
+        self.free.restore(&snapshot.free);
+        self.guard_len = snapshot.guard_len;
+        self.publish_stats();
+    }
+
+    /// Move up to `amount` of free space to `other`, largest intervals first. Only
+    /// free space inside `other.range()` is moved. Return amount actually moved.
+    pub fn transfer_to(&mut self, other: &mut Subranges, amount: u64) -> u64 {
+        let moved = self
+            .free
+            .transfer_within(&mut other.free, amount, other.range);
+        self.publish_stats();
+        other.publish_stats();
+        moved
+    }
+
+    /// Move full range, all free intervals, all taken intervals and all watchpoint ranges
+    /// by `delta`.
+    /// Fail without any changes if some interval would leave `u64` bounds.
+    ///
+    /// **Intervals, returned by takes before the call, are not updated.** Caller must
+    /// shift every interval it holds by the same `delta` before erasing it.
+    pub fn rebase(&mut self, delta: i64) -> Result<(), RebaseError> {
+        let range = IntervalsCollection::shifted(&self.range, delta)?;
+        let watched = self
+            .watchpoints
+            .iter()
+            .map(|watchpoint| IntervalsCollection::shifted(&watchpoint.range, delta))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.free.rebase(delta)?;
+        self.range = range;
+        for (watchpoint, range) in self.watchpoints.iter_mut().zip(watched) {
+            watchpoint.range = range;
+        }
+        Ok(())
+    }
+
+    /// Deterministic hash of state, stable across versions and platforms.
+    ///
+    /// Same as `IntervalsCollection::state_hash` with `start` and `len()` of full range
+    /// written before free intervals.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        hasher.write(self.range.start());
+        hasher.write(self.range.len());
+        self.free.hash_free(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Full range.
+    pub fn range(&self) -> Interval {
+        self.range
+    }
+
+    /// Total length of free subranges. Takes `O(1)` time.
+    pub fn free_len(&self) -> u64 {
+        self.free.free_len()
+    }
+
+    /// Length of the longest free subrange, or 0. The longest take, which can
+    /// succeed, is guard shorter.
+    pub fn largest_free_len(&self) -> u64 {
+        self.free.largest_free().map_or(0, |int| int.len())
+    }
+
+    /// Same as `IntervalsCollection::fragmentation` of free subranges.
+    pub fn fragmentation(&self) -> f64 {
+        self.free.fragmentation()
+    }
+
+    /// Same as `IntervalsCollection::external_fragmentation_for` of free subranges.
+    /// Guard isn't added to `len`.
+    pub fn external_fragmentation_for(&self, len: u64) -> f64 {
+        self.free.external_fragmentation_for(len)
+    }
+
+    /// Number of free subranges.
+    pub fn free_count(&self) -> usize {
+        self.free.count()
+    }
+
+    /// True if no free subrange is left.
+    pub fn is_fully_used(&self) -> bool {
+        self.free.is_empty()
     }
 
     /// Length of full range.
     pub fn len(&self) -> u64 {
-        self.len
+        self.range.len()
     }
 
     /// True if `self.len()` is zero.
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.range.is_empty()
+    }
+}
+
+/// Saved state of `Subranges`, see `Subranges::snapshot`.
+#[derive(Debug, Clone)]
+pub struct SubrangesSnapshot {
+    range: Interval,
+    free: IntervalsSnapshot,
+    guard_len: u64,
+}
+
+/// Result of `Subranges::simulate_erase`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SimulatedResult {
+    /// Free interval, which would contain erased subrange.
+    pub merged_block: Interval,
+    /// Length of the largest free interval after erasing.
+    pub new_largest_free: u64,
+}
+
+/// Error of `Subranges` operations on specific subranges.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SubrangeError {
+    /// Subrange (or its guard) isn't free.
+    NotFree(Interval),
+    /// Taking subranges would leave less than `Subranges::reserve()` free.
+    WouldBreachReserve,
+}
+
+impl fmt::Display for SubrangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubrangeError::NotFree(subrange) => write!(f, "subrange {} isn't free", subrange),
+            SubrangeError::WouldBreachReserve => f.write_str("free space would drop below reserve"),
+        }
+    }
+}
+
+impl Error for SubrangeError {}
+
+/// Error of `Subranges::take_free_no_cross_subrange`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NoCrossError {
+    /// Requested length is bigger than boundary, so it can't fit into one window.
+    LongerThanBoundary,
+    /// There is no free subrange, which fits into one window.
+    NoFreeSubrange,
+    /// Taking subrange would leave less than `Subranges::reserve()` free.
+    WouldBreachReserve,
+}
+
+impl fmt::Display for NoCrossError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoCrossError::LongerThanBoundary => f.write_str("length is bigger than boundary"),
+            NoCrossError::NoFreeSubrange => f.write_str("no free subrange within one window"),
+            NoCrossError::WouldBreachReserve => f.write_str("free space would drop below reserve"),
+        }
+    }
+}
+
+impl Error for NoCrossError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::Interval;
+    use crate::collection::{InsertError, RebaseError};
+    use crate::{NoCrossError, SimulatedResult, SubrangeError, Subranges};
+
+    #[test]
+    fn take_free_no_cross_subrange() {
+        let mut subranges = Subranges::new(Interval::new(0, 64));
+        let int = subranges.take_free_no_cross_subrange(10, 1, 16).unwrap();
+        assert_eq!(int, Interval::new(0, 10));
+        let int = subranges.take_free_no_cross_subrange(10, 1, 16).unwrap();
+        assert_eq!(int, Interval::new(16, 10));
+
+        let res = subranges.take_free_no_cross_subrange(17, 1, 16);
+        assert_eq!(res, Err(NoCrossError::LongerThanBoundary));
+        let res = subranges.take_free_no_cross_subrange(16, 1, 16);
+        assert_eq!(res, Ok(Interval::new(32, 16)));
+        let res = subranges.take_free_no_cross_subrange(16, 1, 16);
+        assert_eq!(res, Ok(Interval::new(48, 16)));
+        let res = subranges.take_free_no_cross_subrange(7, 1, 16);
+        assert_eq!(res, Err(NoCrossError::NoFreeSubrange));
+    }
+
+    #[test]
+    fn guard() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 4);
+        let a = subranges.take_free_subrange(8).unwrap();
+        assert_eq!(a, Interval::new(0, 8));
+        let b = subranges.take_free_align_subrange(3, 8).unwrap();
+        assert_eq!(b, Interval::new(16, 3));
+        assert_eq!(subranges.guard_len(), 8);
+        assert_eq!(subranges.freeze().free_len(), 32 - 8 - 3 - 8);
+
+        // 13 free integers are left, but only 9 fit before the guard.
+        assert!(subranges.take_free_subrange(10).is_none());
+
+        subranges.erase_subrange(a);
+        subranges.erase_subrange(b);
+        assert_eq!(subranges.guard_len(), 0);
+        let free: Vec<_> = subranges.freeze().free_subranges().copied().collect();
+        assert_eq!(free, vec![Interval::new(0, 32)]);
+    }
+
+    #[test]
+    fn guard_no_cross() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 4);
+        let res = subranges.take_free_no_cross_subrange(13, 1, 16);
+        assert_eq!(res, Err(NoCrossError::LongerThanBoundary));
+        let res = subranges.take_free_no_cross_subrange(12, 1, 16);
+        assert_eq!(res, Ok(Interval::new(0, 12)));
+    }
+
+    #[test]
+    fn rebase() {
+        use std::sync::{Arc, Mutex};
+
+        let mut subranges = Subranges::new(Interval::new(100, 50));
+        let a = subranges.take_free_subrange(10).unwrap();
+        subranges.take_free_subrange(10).unwrap();
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let watch_hits = hits.clone();
+        subranges.add_watchpoint(Interval::new(120, 10), move |i| {
+            watch_hits.lock().unwrap().push(*i)
+        });
+
+        subranges.rebase(-100).unwrap();
+        assert_eq!(subranges.range(), Interval::new(0, 50));
+        let free: Vec<_> = subranges.freeze().free_subranges().copied().collect();
+        assert_eq!(free, vec![Interval::new(20, 30)]);
+        let watched = subranges.take_free_subrange(10).unwrap();
+        assert_eq!(watched, Interval::new(20, 10));
+        assert_eq!(*hits.lock().unwrap(), vec![watched]);
+
+        assert_eq!(subranges.rebase(-1), Err(RebaseError::Underflow));
+        subranges.rebase(i64::MAX).unwrap();
+        assert_eq!(subranges.rebase(i64::MAX), Err(RebaseError::Overflow));
+        subranges.rebase(-i64::MAX).unwrap();
+        assert_eq!(subranges.range(), Interval::new(0, 50));
+
+        subranges.erase_subrange(Interval::new(a.start() - 100, a.len()));
+        let free: Vec<_> = subranges.freeze().free_subranges().copied().collect();
+        assert_eq!(free, vec![Interval::new(0, 10), Interval::new(30, 20)]);
+        assert_eq!(hits.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn state_hash() {
+        let mut a = Subranges::new(Interval::new(0, 100));
+        let b = Subranges::new(Interval::new(0, 100));
+        let c = Subranges::new(Interval::new(1, 100));
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_ne!(a.state_hash(), c.state_hash());
+
+        let int = a.take_free_subrange(10).unwrap();
+        assert_ne!(a.state_hash(), b.state_hash());
+        a.erase_subrange(int);
+        assert_eq!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn transfer_to() {
+        let mut a = Subranges::new(Interval::new(0, 100));
+        let mut b = Subranges::new(Interval::new(50, 100));
+        b.take_free_subrange(100).unwrap();
+        a.take_free_subrange(40).unwrap();
+        a.take_free_subrange(20).unwrap();
+
+        assert_eq!(a.transfer_to(&mut b, 100), 40);
+        assert_eq!(a.freeze().free_len(), 0);
+        let free: Vec<_> = b.freeze().free_subranges().copied().collect();
+        assert_eq!(free, vec![Interval::new(60, 40)]);
+    }
+
+    #[test]
+    fn simulate_erase() {
+        let mut subranges = Subranges::new(Interval::new(0, 100));
+        let a = subranges.take_free_subrange(10).unwrap();
+        let b = subranges.take_free_subrange(20).unwrap();
+        subranges.take_free_subrange(10).unwrap();
+
+        let result = subranges.simulate_erase(a);
+        let expected = SimulatedResult {
+            merged_block: a,
+            new_largest_free: 60,
+        };
+        assert_eq!(result, expected);
+
+        subranges.erase_subrange(a);
+        let result = subranges.simulate_erase(b);
+        let expected = SimulatedResult {
+            merged_block: Interval::new(0, 30),
+            new_largest_free: 60,
+        };
+        assert_eq!(result, expected);
+        assert_eq!(subranges.freeze().free_len(), 70);
+    }
+
+    #[test]
+    fn reserve() {
+        let mut subranges = Subranges::new(Interval::new(0, 100));
+        subranges.set_reserve(20);
+        assert!(subranges.take_free_subrange(81).is_none());
+        assert!(subranges.take_free_align_subrange(81, 1).is_none());
+        assert_eq!(subranges.take_free_subrange(50), Some(Interval::new(0, 50)));
+        assert_eq!(subranges.take_free_align_subrange(30, 2), Some(Interval::new(50, 30)));
+        assert!(subranges.take_free_subrange(1).is_none());
+        let res = subranges.take_free_no_cross_subrange(1, 1, 16);
+        assert_eq!(res, Err(NoCrossError::WouldBreachReserve));
+
+        assert_eq!(subranges.take_from_reserve(15, 1), Some(Interval::new(80, 15)));
+        assert_eq!(subranges.take_from_reserve(5, 1), Some(Interval::new(95, 5)));
+        assert!(subranges.take_from_reserve(1, 1).is_none());
+    }
+
+    #[test]
+    fn plan_allocations() {
+        let requests = [(1, 1), (3, 1), (3, 1)];
+        let fragmented = || {
+            let mut subranges = Subranges::new(Interval::new(0, 8));
+            subranges.take_free_subrange(3).unwrap();
+            subranges.take_free_subrange(1).unwrap();
+            subranges.erase_subrange(Interval::new(0, 3));
+            subranges
+        };
+
+        let mut greedy = fragmented();
+        let taken: Vec<_> = requests
+            .iter()
+            .map(|&(length, align)| greedy.take_free_align_subrange(length, align))
+            .collect();
+        assert!(taken[2].is_none());
+
+        let mut subranges = fragmented();
+        let plan = subranges.plan_allocations(&requests).unwrap();
+        let expected = vec![Interval::new(7, 1), Interval::new(0, 3), Interval::new(4, 3)];
+        assert_eq!(plan, expected);
+        assert_eq!(subranges.freeze().free_len(), 7);
+        subranges.commit_plan(&plan).unwrap();
+        assert_eq!(subranges.freeze().free_len(), 0);
+        assert!(subranges.plan_allocations(&[(1, 1)]).is_none());
+
+        let mut subranges = Subranges::new(Interval::new(1, 40));
+        subranges.set_min_remainder(4);
+        let plan = subranges.plan_allocations(&[(8, 4)]).unwrap();
+        assert_eq!(plan, vec![Interval::new(4, 8)]);
+        subranges.commit_plan(&plan).unwrap();
+        assert_eq!(subranges.free_len(), 40 - 8);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn commit_plan_is_atomic() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 20), 2);
+        let plan = subranges.plan_allocations(&[(4, 4), (6, 1)]).unwrap();
+        assert_eq!(plan, vec![Interval::new(8, 4), Interval::new(0, 6)]);
+
+        let overlapping = [plan[0], plan[1], Interval::new(5, 2)];
+        let res = subranges.commit_plan(&overlapping);
+        assert_eq!(res, Err(SubrangeError::NotFree(Interval::new(5, 2))));
+        assert_eq!(subranges.freeze().free_len(), 20);
+        assert_eq!(subranges.guard_len(), 0);
+
+        subranges.commit_plan(&plan).unwrap();
+        assert_eq!(subranges.guard_len(), 4);
+        assert_eq!(subranges.freeze().free_len(), 6);
+    }
+
+    #[test]
+    fn watchpoints() {
+        use std::sync::{Arc, Mutex};
+
+        let hits = Arc::new(Mutex::new(Vec::new()));
+        let mut subranges = Subranges::new(Interval::new(0, 100));
+        let low_hits = hits.clone();
+        let low = subranges.add_watchpoint(Interval::new(0, 10), move |i| {
+            low_hits.lock().unwrap().push(("low", *i))
+        });
+        let mid_hits = hits.clone();
+        subranges.add_watchpoint(Interval::new(5, 15), move |i| {
+            mid_hits.lock().unwrap().push(("mid", *i))
+        });
+
+        let a = subranges.take_free_subrange(8).unwrap();
+        let b = subranges.take_free_subrange(8).unwrap();
+        let c = subranges.take_free_subrange(8).unwrap();
+        subranges.take_free_subrange(8).unwrap();
+        let expected = vec![("low", a), ("mid", a), ("low", b), ("mid", b), ("mid", c)];
+        assert_eq!(*hits.lock().unwrap(), expected);
+
+        assert!(subranges.remove_watchpoint(low));
+        assert!(!subranges.remove_watchpoint(low));
+        hits.lock().unwrap().clear();
+        subranges.erase_subrange(a);
+        let a = subranges.take_free_align_subrange(8, 1).unwrap();
+        assert_eq!(*hits.lock().unwrap(), vec![("mid", a)]);
+    }
+
+    #[test]
+    fn stats_from_other_thread() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 1024), 1);
+        let stats = subranges.stats();
+        assert_eq!(stats.free_len(), 1024);
+
+        let worker = std::thread::spawn(move || {
+            for _ in 0..200 {
+                let taken: Vec<_> = (1..40)
+                    .filter_map(|len| subranges.take_free_subrange(len))
+                    .collect();
+                for int in taken {
+                    subranges.erase_subrange(int);
+                }
+            }
+            subranges.take_free_subrange(100).unwrap();
+            assert!(subranges.take_free_subrange(1024).is_none());
+            subranges
+        });
+        while !worker.is_finished() {
+            assert!(stats.free_len() <= 1024);
+            assert!(stats.used_len() <= stats.peak_used());
+            assert!(stats.peak_used() <= 1024);
+        }
+        let subranges = worker.join().unwrap();
+
+        assert_eq!(stats.free_len(), 1024 - 101);
+        assert_eq!(stats.used_len(), 101);
+        assert_eq!(stats.peak_used(), (1..40).map(|len| len + 1).sum::<u64>());
+        assert_eq!(stats.failed_takes(), 1);
+        assert_eq!(subranges.stats().free_len(), stats.free_len());
+    }
+
+    #[test]
+    fn take_free_excluding_subrange() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 2);
+        let scrubbed = [Interval::new(4, 8), Interval::new(20, 4)];
+
+        let int = subranges
+            .take_free_excluding_subrange(6, 1, &scrubbed)
+            .unwrap();
+        assert_eq!(int, Interval::new(12, 6));
+        assert!(subranges
+            .take_free_excluding_subrange(7, 1, &scrubbed)
+            .is_none());
+        let int = subranges.take_free_subrange(7).unwrap();
+        assert_eq!(int, Interval::new(0, 7));
+    }
+
+    #[test]
+    fn take_max_subrange() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 2);
+        let a = subranges.take_free_subrange(6).unwrap();
+        subranges.take_free_subrange(2).unwrap();
+        subranges.erase_subrange(a);
+
+        assert_eq!(subranges.take_max_subrange(), Some(Interval::new(12, 18)));
+        assert_eq!(subranges.take_max_subrange(), Some(Interval::new(0, 6)));
+        assert_eq!(subranges.take_max_subrange(), None);
+        assert_eq!(subranges.stats().free_len(), 0);
+
+        let mut subranges = Subranges::with_guard(Interval::new(0, 2), 2);
+        assert_eq!(subranges.take_max_subrange(), None);
+        assert_eq!(subranges.stats().failed_takes(), 1);
+    }
+
+    #[test]
+    fn unusual_aligns() {
+        let mut subranges = Subranges::new(Interval::new(1, 100));
+        assert_eq!(subranges.take_free_align_subrange(2, 0), Some(Interval::new(1, 2)));
+        assert_eq!(subranges.take_free_align_subrange(2, 1), Some(Interval::new(3, 2)));
+        assert_eq!(subranges.take_free_align_subrange(2, 3), Some(Interval::new(6, 2)));
+        assert_eq!(subranges.take_free_align_subrange(2, 24), Some(Interval::new(24, 2)));
+        assert_eq!(subranges.take_free_align_subrange(2, 128), None);
+    }
+
+    #[test]
+    fn reset() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 2);
+        subranges.take_free_subrange(10).unwrap();
+        subranges.take_free_subrange(10).unwrap();
+        assert!(subranges.take_free_subrange(10).is_none());
+        let stats = subranges.stats();
+
+        subranges.reset();
+        assert_eq!(subranges.guard_len(), 0);
+        assert_eq!(stats.free_len(), 32);
+        assert_eq!(stats.failed_takes(), 1);
+        assert_eq!(subranges.take_free_subrange(30), Some(Interval::new(0, 30)));
+    }
+
+    #[test]
+    fn free_len() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 1);
+        assert_eq!((subranges.free_len(), subranges.free_count()), (32, 1));
+        let a = subranges.take_free_subrange(7).unwrap();
+        subranges.take_free_subrange(7).unwrap();
+        subranges.erase_subrange(a);
+        assert_eq!((subranges.free_len(), subranges.free_count()), (24, 2));
+        assert!(!subranges.is_fully_used());
+        assert!(subranges.take_free_subrange(15).is_some());
+        assert!(subranges.take_free_subrange(7).is_some());
+        assert!(subranges.is_fully_used());
+        assert_eq!((subranges.free_len(), subranges.free_count()), (0, 0));
+        assert_eq!(subranges.largest_free_len(), 0);
+
+        let mut subranges = Subranges::new(Interval::new(1, 40));
+        subranges.take_free_align_subrange(4, 16).unwrap();
+        assert_eq!(subranges.largest_free_len(), 21);
+        subranges.take_free_align_subrange(10, 8).unwrap();
+        assert_eq!(subranges.largest_free_len(), 15);
+        assert_eq!(subranges.fragmentation(), 1.0 - 15.0 / 26.0);
+        assert_eq!(subranges.external_fragmentation_for(8), 11.0 / 26.0);
+    }
+
+    #[test]
+    fn try_erase_subrange() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 2);
+        let a = subranges.take_free_subrange(6).unwrap();
+        let b = subranges.take_free_subrange(6).unwrap();
+        assert_eq!(subranges.try_erase_subrange(a), Ok(()));
+        let double = subranges.try_erase_subrange(a);
+        assert_eq!(double, Err(InsertError::Overlaps(Interval::new(0, 8))));
+        let partial = subranges.try_erase_subrange(Interval::new(4, 6));
+        assert_eq!(partial, Err(InsertError::Overlaps(Interval::new(0, 8))));
+        assert_eq!(subranges.guard_len(), 2);
+        assert_eq!(subranges.free_len(), 8 + 16);
+
+        assert_eq!(subranges.try_erase_subrange(b), Ok(()));
+        assert_eq!(subranges.free_count(), 1);
+        assert_eq!(subranges.stats().free_len(), 32);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 64), 2);
+        let a = subranges.take_free_subrange(6).unwrap();
+        subranges.take_free_align_subrange(6, 16).unwrap();
+        let snapshot = subranges.snapshot();
+        let hash = subranges.state_hash();
+
+        subranges.erase_subrange(a);
+        subranges.take_free_subrange(20).unwrap();
+        subranges.take_free_subrange(3).unwrap();
+        subranges.restore(&snapshot);
+        assert_eq!(subranges.state_hash(), hash);
+        assert_eq!(subranges.guard_len(), 4);
+        assert_eq!(subranges.stats().free_len(), 64 - 16);
+        assert_eq!(subranges.free_len(), 64 - 16);
+    }
+
+    #[test]
+    fn min_remainder() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 4097), 1);
+        subranges.set_min_remainder(2);
+        assert_eq!(subranges.take_free_subrange(4095), Some(Interval::new(0, 4096)));
+        assert!(subranges.is_fully_used());
+        subranges.erase_subrange(Interval::new(0, 4096));
+        assert_eq!(subranges.free_len(), 4097);
+        assert_eq!(subranges.largest_free_len(), 4097);
+
+        let mut subranges = Subranges::new(Interval::new(0, 16));
+        subranges.set_min_remainder(2);
+        subranges.set_reserve(1);
+        assert!(subranges.take_free_subrange(15).is_none());
+        assert_eq!(subranges.free_len(), 16);
+        assert_eq!(subranges.take_free_align_subrange(4, 4), Some(Interval::new(0, 4)));
+        subranges.set_min_remainder(5);
+        subranges.set_reserve(8);
+        assert!(subranges.take_free_align_subrange(3, 8).is_none());
+        assert_eq!(subranges.free_len(), 12);
+        assert_eq!(subranges.free_count(), 1);
+        subranges.set_reserve(0);
+        assert_eq!(subranges.take_free_align_subrange(3, 8), Some(Interval::new(4, 7)));
+
+        let mut subranges = Subranges::new(Interval::new(1, 17));
+        subranges.set_min_remainder(2);
+        subranges.set_reserve(8);
+        assert!(subranges.take_free_align_subrange(9, 8).is_none());
+        assert_eq!(subranges.padding_created(), 0);
+        assert_eq!(subranges.free_len(), 17);
+
+        let mut subranges = Subranges::new(Interval::new(0, 16));
+        subranges.set_min_remainder(3);
+        subranges.set_reserve(1);
+        let res = subranges.take_free_no_cross_subrange(14, 1, 16);
+        assert_eq!(res, Err(NoCrossError::WouldBreachReserve));
+        assert!(subranges.take_free_excluding_subrange(14, 1, &[]).is_none());
+        assert_eq!(subranges.free_len(), 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn restore_other_range() {
+        let snapshot = Subranges::new(Interval::new(0, 10)).snapshot();
+        Subranges::new(Interval::new(0, 20)).restore(&snapshot);
+    }
+
+    #[test]
+    fn free_within() {
+        let mut subranges = Subranges::new(Interval::new(16, 32));
+        subranges.take_free_subrange(8).unwrap();
+        let b = subranges.take_free_subrange(8).unwrap();
+        subranges.take_free_subrange(8).unwrap();
+        subranges.erase_subrange(b);
+
+        let query = Interval::new(20, 16);
+        assert_eq!(subranges.free_within(query), 8);
+        assert_eq!(subranges.used_within(query), 8);
+        let query = Interval::new(0, 100);
+        assert_eq!(subranges.free_within(query), 16);
+        assert_eq!(subranges.used_within(query), 16);
+        let query = Interval::new(48, 10);
+        assert_eq!(subranges.free_within(query), 0);
+        assert_eq!(subranges.used_within(query), 0);
+    }
+
+    #[test]
+    fn empty_takes() {
+        let mut subranges = Subranges::with_guard(Interval::new(0, 32), 2);
+        assert_eq!(subranges.take_free_subrange(0), None);
+        assert_eq!(subranges.take_free_align_subrange(0, 8), None);
+        assert_eq!(subranges.take_from_reserve(0, 1), None);
+        assert_eq!(subranges.take_free_excluding_subrange(0, 1, &[]), None);
+        let res = subranges.take_free_no_cross_subrange(0, 1, 16);
+        assert_eq!(res, Err(NoCrossError::NoFreeSubrange));
+        assert_eq!(subranges.plan_allocations(&[(4, 1), (0, 1)]), None);
+        assert_eq!(subranges.guard_len(), 0);
+
+        let a = subranges.take_free_subrange(4).unwrap();
+        subranges.erase_subrange(Interval::new(20, 0));
+        assert_eq!(subranges.guard_len(), 2);
+        assert_eq!(subranges.free_within(subranges.range()), 32 - 6);
+        subranges.erase_subrange(a);
+        assert_eq!(subranges.free_within(subranges.range()), 32);
+    }
+}
@@ -0,0 +1,239 @@
+use crate::interval::Interval;
+use crate::Subranges;
+use std::cmp;
+
+/// Identifies heap, added by `MultiSubranges::add_heap`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct HeapId(u64);
+
+/// Order, in which `MultiSubranges::take` tries heaps.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub enum HeapPolicy {
+    /// Try heaps in order they were added.
+    #[default]
+    FirstFit,
+    /// Try heaps with more free space first. Ties are tried in order they were added.
+    MostFree,
+}
+
+/// One of `MultiSubranges` heaps.
+pub struct Heap {
+    id: HeapId,
+    name: String,
+    subranges: Subranges,
+    budget: Option<u64>,
+    used: u64,
+}
+
+impl Heap {
+    pub fn id(&self) -> HeapId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn subranges(&self) -> &Subranges {
+        &self.subranges
+    }
+
+    /// Max total length of intervals taken through `MultiSubranges::take`, guards included.
+    pub fn budget(&self) -> Option<u64> {
+        self.budget
+    }
+
+    /// Total length of intervals taken through `MultiSubranges::take` and not erased,
+    /// guards included.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Length charged to budget for taken `length` integers.
+    fn charged(&self, length: u64) -> u64 {
+        length.saturating_add(self.subranges.guard())
+    }
+
+    fn fits_budget(&self, length: u64) -> bool {
+        match self.budget {
+            Some(budget) => self
+                .used
+                .checked_add(length)
+                .is_some_and(|used| used <= budget),
+            None => true,
+        }
+    }
+}
+
+/// Takes subranges from several `Subranges` heaps, respecting per-heap budgets.
+#[derive(Default)]
+pub struct MultiSubranges {
+    heaps: Vec<Heap>,
+    policy: HeapPolicy,
+    next_id: u64,
+}
+
+impl MultiSubranges {
+    /// Add heap with lowest priority. Takes from it, guards included, never exceed
+    /// `budget`. Taken intervals may be longer than requested, see
+    /// `Subranges::set_min_remainder`: they are charged by their real length.
+    pub fn add_heap(
+        &mut self,
+        name: impl Into<String>,
+        subranges: Subranges,
+        budget: Option<u64>,
+    ) -> HeapId {
+        let id = HeapId(self.next_id);
+        self.next_id += 1;
+        self.heaps.push(Heap {
+            id,
+            name: name.into(),
+            subranges,
+            budget,
+            used: 0,
+        });
+        id
+    }
+
+    /// Remove heap and return its `Subranges`. Return `None` if it was already removed.
+    pub fn remove_heap(&mut self, id: HeapId) -> Option<Subranges> {
+        let index = self.heaps.iter().position(|heap| heap.id == id)?;
+        Some(self.heaps.remove(index).subranges)
+    }
+
+    pub fn policy(&self) -> HeapPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: HeapPolicy) {
+        self.policy = policy;
+    }
+
+    /// Take `length` integers aligned to `align` from the first heap, which has
+    /// enough free space and budget. Heaps are tried in order, set by policy.
+    pub fn take(&mut self, length: u64, align: u64) -> Option<(HeapId, Interval)> {
+        let mut order: Vec<usize> = (0..self.heaps.len()).collect();
+        if self.policy == HeapPolicy::MostFree {
+            let heaps = &self.heaps;
+            order.sort_by_key(|&i| cmp::Reverse(heaps[i].subranges.free_len()));
+        }
+        for i in order {
+            let heap = &mut self.heaps[i];
+            if !heap.fits_budget(heap.charged(length)) {
+                continue;
+            }
+            if let Some(int) = heap.subranges.take_free_align_subrange(length, align) {
+                let charged = heap.charged(int.len());
+                if !heap.fits_budget(charged) {
+                    heap.subranges.erase_subrange(int);
+                    continue;
+                }
+                heap.used += charged;
+                return Some((heap.id, int));
+            }
+        }
+        None
+    }
+
+    /// Erase `subrange`, taken from heap `id`. Return `false` if there is no such heap.
+    pub fn erase(&mut self, id: HeapId, subrange: Interval) -> bool {
+        match self.heaps.iter_mut().find(|heap| heap.id == id) {
+            Some(heap) => {
+                heap.used = heap.used.saturating_sub(heap.charged(subrange.len()));
+                heap.subranges.erase_subrange(subrange);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn heap(&self, id: HeapId) -> Option<&Heap> {
+        self.heaps.iter().find(|heap| heap.id == id)
+    }
+
+    /// Heaps in order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &Heap> {
+        self.heaps.iter()
+    }
+
+    /// Total `used()` of all heaps.
+    pub fn used(&self) -> u64 {
+        self.heaps.iter().map(|heap| heap.used).sum()
+    }
+
+    /// Total free length of all heaps.
+    pub fn free_len(&self) -> u64 {
+        let free = self.heaps.iter().map(|heap| heap.subranges.free_len());
+        free.sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::Interval;
+    use crate::multi::{HeapPolicy, MultiSubranges};
+    use crate::Subranges;
+
+    #[test]
+    fn budget_fallback() {
+        let mut multi = MultiSubranges::default();
+        let device = multi.add_heap("device", Subranges::new(Interval::new(0, 64)), Some(16));
+        let host = multi.add_heap("host", Subranges::new(Interval::new(1000, 32)), None);
+
+        assert_eq!(multi.take(8, 1), Some((device, Interval::new(0, 8))));
+        assert_eq!(multi.take(8, 8), Some((device, Interval::new(8, 8))));
+        assert_eq!(multi.take(4, 1), Some((host, Interval::new(1000, 4))));
+        assert_eq!(multi.take(32, 1), None);
+        assert_eq!(multi.heap(device).unwrap().used(), 16);
+        assert_eq!(multi.used(), 20);
+        assert_eq!(multi.free_len(), 64 - 16 + 32 - 4);
+
+        assert!(multi.erase(device, Interval::new(0, 8)));
+        assert_eq!(multi.take(4, 1), Some((device, Interval::new(0, 4))));
+
+        let removed = multi.remove_heap(host).unwrap();
+        assert_eq!(removed.range(), Interval::new(1000, 32));
+        assert!(!multi.erase(host, Interval::new(1000, 4)));
+        let names: Vec<_> = multi.iter().map(|heap| heap.name()).collect();
+        assert_eq!(names, ["device"]);
+    }
+
+    #[test]
+    fn budget_counts_guards_and_remainders() {
+        let mut multi = MultiSubranges::default();
+        let guarded = multi.add_heap(
+            "guarded",
+            Subranges::with_guard(Interval::new(0, 64), 2),
+            Some(20),
+        );
+        assert!(multi.take(8, 1).is_some());
+        assert_eq!(multi.heap(guarded).unwrap().used(), 10);
+        assert!(multi.take(8, 1).is_some());
+        assert_eq!(multi.take(1, 1), None);
+        assert!(multi.erase(guarded, Interval::new(0, 8)));
+        assert_eq!(multi.heap(guarded).unwrap().used(), 10);
+
+        let mut subranges = Subranges::new(Interval::new(0, 13));
+        subranges.set_min_remainder(4);
+        let mut multi = MultiSubranges::default();
+        let rounded = multi.add_heap("rounded", subranges, Some(11));
+        let other = multi.add_heap("other", Subranges::new(Interval::new(100, 16)), None);
+        assert_eq!(multi.take(10, 1), Some((other, Interval::new(100, 10))));
+        let heap = multi.heap(rounded).unwrap();
+        assert_eq!((heap.used(), heap.subranges().free_len()), (0, 13));
+        assert_eq!(multi.take(9, 1), Some((rounded, Interval::new(0, 9))));
+    }
+
+    #[test]
+    fn most_free() {
+        let mut multi = MultiSubranges::default();
+        let small = multi.add_heap("small", Subranges::new(Interval::new(0, 16)), None);
+        let big = multi.add_heap("big", Subranges::new(Interval::new(100, 24)), None);
+        multi.set_policy(HeapPolicy::MostFree);
+
+        assert_eq!(multi.take(10, 1), Some((big, Interval::new(100, 10))));
+        assert_eq!(multi.take(4, 1), Some((small, Interval::new(0, 4))));
+        assert_eq!(multi.take(14, 1), Some((big, Interval::new(110, 14))));
+        assert_eq!(multi.take(14, 1), None);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::interval::Interval;
+use std::cmp;
+
+const BIAS: u64 = 1 << 63;
+
+/// Signed integer interval `[start; end)`.
+///
+/// `unsigned` maps it to `Interval` preserving order, so `Subranges` can manage
+/// signed ranges. Alignment to powers of two is preserved by the mapping too.
+///
+/// ```
+/// use subranges::signed::SignedInterval;
+/// use subranges::Subranges;
+///
+/// let range = SignedInterval::new(-1024, 2048);
+/// let mut subranges = Subranges::new(range.unsigned());
+/// let taken = subranges.take_free_align_subrange(16, 64).unwrap();
+/// assert_eq!(SignedInterval::from_unsigned(taken), SignedInterval::new(-1024, 16));
+/// ```
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SignedInterval {
+    start: i64,
+    end: i64,
+}
+
+impl SignedInterval {
+    /// Create new interval [`start`; start + length).
+    /// # Panics
+    /// * Panics if `start + length` doesn't fit into `i64`
+    pub fn new(start: i64, length: u64) -> Self {
+        Self::checked_new(start, length).expect("Interval end must fit into i64")
+    }
+
+    /// Create new interval [`start`; start + length).
+    /// Return `None` if `start + length` doesn't fit into `i64`.
+    pub fn checked_new(start: i64, length: u64) -> Option<Self> {
+        let end = start.checked_add_unsigned(length)?;
+        Some(Self { start, end })
+    }
+
+    /// First integer in `self`.
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    /// Integer after last integer in `self`.
+    pub fn end(&self) -> i64 {
+        self.end
+    }
+
+    /// Count of integers in `self`.
+    pub fn len(&self) -> u64 {
+        self.end.abs_diff(self.start)
+    }
+
+    /// True if self.len() == 0.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Return `true` if `self` contains `p`. Else `false`.
+    pub fn contains(&self, p: i64) -> bool {
+        p >= self.start && p < self.end
+    }
+
+    /// Return interval from min `start` to max `end`.
+    pub fn connect(&self, other: &Self) -> Self {
+        Self {
+            start: cmp::min(self.start, other.start),
+            end: cmp::max(self.end, other.end),
+        }
+    }
+
+    /// Split `self` into `[self.start; self.start + length)` and the rest.
+    /// # Panics
+    /// * Panics if `length` > `self.len()`
+    pub fn split(&self, length: u64) -> (Self, Self) {
+        assert!(
+            length <= self.len(),
+            "Split length must be <= interval length"
+        );
+        let middle = self.start.wrapping_add_unsigned(length);
+        let left = Self {
+            start: self.start,
+            end: middle,
+        };
+        let right = Self {
+            start: middle,
+            end: self.end,
+        };
+        (left, right)
+    }
+
+    /// Distance from `start` to the least multiple of `align`, which is not less than
+    /// `start`. So for negative starts padding rounds toward positive infinity too.
+    /// # Panics
+    /// * Panics if `align` is 0
+    pub fn align_pad(&self, align: u64) -> u64 {
+        assert!(align > 0, "Align must be > 0");
+        (-i128::from(self.start)).rem_euclid(i128::from(align)) as u64
+    }
+
+    /// Same integers, shifted by `2^63`: `i64::MIN` becomes `0`.
+    pub fn unsigned(&self) -> Interval {
+        Interval::new(Self::biased(self.start), self.len())
+    }
+
+    /// Inverse of `unsigned`.
+    pub fn from_unsigned(interval: Interval) -> Self {
+        Self {
+            start: (interval.start() ^ BIAS) as i64,
+            end: (interval.end() ^ BIAS) as i64,
+        }
+    }
+
+    fn biased(value: i64) -> u64 {
+        value as u64 ^ BIAS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::Interval;
+    use crate::signed::SignedInterval;
+    use crate::Subranges;
+
+    #[test]
+    fn straddle_zero() {
+        let i = SignedInterval::new(-5, 10);
+        assert_eq!(i.end(), 5);
+        assert_eq!(i.len(), 10);
+        assert!(i.contains(-5));
+        assert!(i.contains(0));
+        assert!(!i.contains(5));
+
+        let (left, right) = i.split(5);
+        assert_eq!(left, SignedInterval::new(-5, 5));
+        assert_eq!(right, SignedInterval::new(0, 5));
+        assert_eq!(
+            left.connect(&SignedInterval::new(10, 2)),
+            SignedInterval::new(-5, 17)
+        );
+
+        assert_eq!(i.align_pad(4), 1);
+        assert_eq!(i.align_pad(5), 0);
+        assert_eq!(SignedInterval::new(-8, 1).align_pad(8), 0);
+        assert_eq!(SignedInterval::new(3, 1).align_pad(8), 5);
+    }
+
+    #[test]
+    fn extremes() {
+        let low = SignedInterval::new(i64::MIN, u64::MAX);
+        assert_eq!(low.end(), i64::MAX);
+        assert_eq!(low.len(), u64::MAX);
+        assert_eq!(low.align_pad(1 << 63), 0);
+        assert_eq!(
+            SignedInterval::new(i64::MIN + 1, 1).align_pad(1 << 63),
+            (1 << 63) - 1
+        );
+        assert_eq!(low.unsigned(), Interval::new(0, u64::MAX));
+        assert_eq!(SignedInterval::from_unsigned(low.unsigned()), low);
+        assert_eq!(SignedInterval::checked_new(i64::MAX, 1), None);
+        assert_eq!(
+            SignedInterval::checked_new(-1, 1 << 63),
+            Some(SignedInterval::new(-1, 1 << 63))
+        );
+    }
+
+    #[test]
+    fn subranges_over_signed_range() {
+        let range = SignedInterval::new(-1024, 2048);
+        let mut subranges = Subranges::new(range.unsigned());
+        let a = subranges.take_free_align_subrange(100, 1).unwrap();
+        let b = subranges.take_free_align_subrange(100, 256).unwrap();
+        assert_eq!(
+            SignedInterval::from_unsigned(a),
+            SignedInterval::new(-1024, 100)
+        );
+        assert_eq!(
+            SignedInterval::from_unsigned(b),
+            SignedInterval::new(-768, 100)
+        );
+
+        subranges.erase_subrange(a);
+        let c = subranges.take_free_align_subrange(50, 512).unwrap();
+        assert_eq!(
+            SignedInterval::from_unsigned(c),
+            SignedInterval::new(-1024, 50)
+        );
+
+        let order = |x: i64, y: i64| {
+            let (x, y) = (SignedInterval::new(x, 0), SignedInterval::new(y, 0));
+            x.unsigned().start() < y.unsigned().start()
+        };
+        assert!(order(-1, 0));
+        assert!(order(i64::MIN, -1));
+        assert!(order(0, i64::MAX));
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Lock-free view of `Subranges` counters.
+///
+/// Cheap to clone and safe to share between threads. Counters are updated by the
+/// thread, owning the allocator, with relaxed stores after every take and erase.
+///
+/// Every counter is atomic on its own, but counters are not a consistent snapshot:
+/// while allocator is in use, `free_len() + used_len()` may differ from full length
+/// for a moment. Use `Subranges::freeze` for a consistent snapshot.
+#[derive(Debug, Clone)]
+pub struct StatsHandle {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    free_len: AtomicU64,
+    used_len: AtomicU64,
+    peak_used: AtomicU64,
+    failed_takes: AtomicU64,
+}
+
+impl StatsHandle {
+    pub(crate) fn new(free_len: u64, len: u64) -> Self {
+        let stats = Self {
+            counters: Arc::new(Counters::default()),
+        };
+        stats.publish(free_len, len);
+        stats
+    }
+
+    /// Store new free length. Must be called by the owning thread only.
+    pub(crate) fn publish(&self, free_len: u64, len: u64) {
+        let used_len = len.saturating_sub(free_len);
+        let counters = &self.counters;
+        counters.free_len.store(free_len, Ordering::Relaxed);
+        counters.used_len.store(used_len, Ordering::Relaxed);
+        if used_len > counters.peak_used.load(Ordering::Relaxed) {
+            counters.peak_used.store(used_len, Ordering::Relaxed);
+        }
+    }
+
+    /// Count take, which returned nothing. Must be called by the owning thread only.
+    pub(crate) fn record_failed_take(&self) {
+        let failed = self.counters.failed_takes.load(Ordering::Relaxed);
+        self.counters
+            .failed_takes
+            .store(failed + 1, Ordering::Relaxed);
+    }
+
+    /// Total length of free subranges.
+    pub fn free_len(&self) -> u64 {
+        self.counters.free_len.load(Ordering::Relaxed)
+    }
+
+    /// Total length of taken subranges, guards included.
+    pub fn used_len(&self) -> u64 {
+        self.counters.used_len.load(Ordering::Relaxed)
+    }
+
+    /// Largest `used_len()` seen so far.
+    pub fn peak_used(&self) -> u64 {
+        self.counters.peak_used.load(Ordering::Relaxed)
+    }
+
+    /// Number of takes, which returned nothing.
+    pub fn failed_takes(&self) -> u64 {
+        self.counters.failed_takes.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::StatsHandle;
+
+    #[test]
+    fn publish() {
+        let stats = StatsHandle::new(10, 10);
+        assert_eq!(stats.used_len(), 0);
+
+        let clone = stats.clone();
+        stats.publish(4, 10);
+        stats.publish(7, 10);
+        assert_eq!(clone.free_len(), 7);
+        assert_eq!(clone.used_len(), 3);
+        assert_eq!(clone.peak_used(), 6);
+
+        stats.record_failed_take();
+        assert_eq!(clone.failed_takes(), 1);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::interval::Interval;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// `Interval`, measured in units `U`.
+///
+/// Intervals in different units have different types, so byte offsets can't be
+/// mixed up with block indices. Conversions are explicit and checked.
+///
+/// ```
+/// use subranges::interval::Interval;
+/// use subranges::typed::TypedInterval;
+///
+/// struct Bytes;
+/// struct Blocks;
+///
+/// let blocks = TypedInterval::<Blocks>::new(Interval::new(2, 3));
+/// let bytes: TypedInterval<Bytes> = blocks.scale_up(4096).unwrap();
+/// assert_eq!(bytes.interval(), Interval::new(8192, 12288));
+/// let back: TypedInterval<Blocks> = bytes.scale_down(4096).unwrap();
+/// assert_eq!(back, blocks);
+/// ```
+pub struct TypedInterval<U> {
+    interval: Interval,
+    unit: PhantomData<fn() -> U>,
+}
+
+impl<U> TypedInterval<U> {
+    /// Mark `interval` as measured in units `U`.
+    pub fn new(interval: Interval) -> Self {
+        Self {
+            interval,
+            unit: PhantomData,
+        }
+    }
+
+    /// Underlying untyped interval.
+    pub fn interval(&self) -> Interval {
+        self.interval
+    }
+
+    /// Convert to units `V`, each of which is `factor` times smaller than `U`.
+    /// Return `None` on overflow.
+    pub fn scale_up<V>(&self, factor: u64) -> Option<TypedInterval<V>> {
+        self.interval.scale(factor).map(TypedInterval::new)
+    }
+
+    /// Convert to units `V`, each of which is `factor` times bigger than `U`.
+    /// Return `None` if `factor` is zero or start or length isn't multiple of `factor`.
+    pub fn scale_down<V>(&self, factor: u64) -> Option<TypedInterval<V>> {
+        self.interval.scale_down(factor).map(TypedInterval::new)
+    }
+}
+
+impl<U> From<TypedInterval<U>> for Interval {
+    fn from(typed: TypedInterval<U>) -> Self {
+        typed.interval
+    }
+}
+
+impl<U> Clone for TypedInterval<U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<U> Copy for TypedInterval<U> {}
+
+impl<U> PartialEq for TypedInterval<U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.interval == other.interval
+    }
+}
+
+impl<U> Eq for TypedInterval<U> {}
+
+impl<U> Hash for TypedInterval<U> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.interval.hash(state)
+    }
+}
+
+impl<U> fmt::Debug for TypedInterval<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedInterval")
+            .field(&self.interval)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval::Interval;
+    use crate::typed::TypedInterval;
+
+    struct Bytes;
+    struct Blocks;
+
+    #[test]
+    fn scale_up() {
+        let blocks = TypedInterval::<Blocks>::new(Interval::new(3, 2));
+        let bytes: TypedInterval<Bytes> = blocks.scale_up(512).unwrap();
+        assert_eq!(bytes.interval(), Interval::new(1536, 1024));
+
+        let huge = TypedInterval::<Blocks>::new(Interval::new(u64::MAX / 4, 1));
+        assert!(huge.scale_up::<Bytes>(2).is_some());
+        assert!(huge.scale_up::<Bytes>(5).is_none());
+        let long = TypedInterval::<Blocks>::new(Interval::new(1, u64::MAX / 2));
+        assert!(long.scale_up::<Bytes>(2).is_none());
+    }
+
+    #[test]
+    fn scale_down() {
+        let bytes = TypedInterval::<Bytes>::new(Interval::new(1024, 4096));
+        let blocks: TypedInterval<Blocks> = bytes.scale_down(512).unwrap();
+        assert_eq!(Interval::from(blocks), Interval::new(2, 8));
+
+        assert!(bytes.scale_down::<Blocks>(0).is_none());
+        assert!(bytes.scale_down::<Blocks>(3).is_none());
+        let unaligned = TypedInterval::<Bytes>::new(Interval::new(1000, 4096));
+        assert!(unaligned.scale_down::<Blocks>(512).is_none());
+        let partial = TypedInterval::<Bytes>::new(Interval::new(1024, 4000));
+        assert!(partial.scale_down::<Blocks>(512).is_none());
+    }
+}